@@ -0,0 +1,55 @@
+#![no_std]
+//! Minimal SEP-40 price feed whose prices are set by hand. Only meant for
+//! tests and local networks.
+use soroban_sdk::{contract, contractimpl, contracttype, Address, Env, Symbol, symbol_short};
+
+/// Asset identifier as defined by SEP-40
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Asset {
+    Stellar(Address),
+    Other(Symbol),
+}
+
+/// Price record as defined by SEP-40
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PriceData {
+    pub price: i128,
+    pub timestamp: u64,
+}
+
+// Storage keys
+const DECIMALS: Symbol = symbol_short!("decimals");
+const PRICE: Symbol = symbol_short!("price");
+
+#[contract]
+pub struct MockOracle;
+
+#[contractimpl]
+impl MockOracle {
+    /// Deploy with the number of decimals prices are quoted in
+    pub fn __constructor(env: Env, decimals: u32) {
+        env.storage().instance().set(&DECIMALS, &decimals);
+    }
+
+    /// Set the latest price of an asset
+    pub fn set_price(env: Env, asset: Asset, price: i128, timestamp: u64) {
+        env.storage().temporary().set(&(PRICE, asset), &PriceData { price, timestamp });
+    }
+
+    /// Remove the price of an asset, as if the feed stopped reporting it
+    pub fn clear_price(env: Env, asset: Asset) {
+        env.storage().temporary().remove(&(PRICE, asset));
+    }
+
+    /// Get the latest price of an asset
+    pub fn lastprice(env: Env, asset: Asset) -> Option<PriceData> {
+        env.storage().temporary().get(&(PRICE, asset))
+    }
+
+    /// Get the number of decimals prices are quoted in
+    pub fn decimals(env: Env) -> u32 {
+        env.storage().instance().get(&DECIMALS).unwrap_or(14)
+    }
+}
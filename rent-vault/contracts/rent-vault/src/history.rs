@@ -0,0 +1,72 @@
+//! Per-user history of record ids, stored in fixed-size pages.
+//!
+//! Recording an id only rewrites the newest page, so the cost stays flat
+//! however long a history grows. Older pages are never written again and are
+//! archived once nobody bumps them; reading an archived page fails until it
+//! is restored.
+
+use soroban_sdk::{contracttype, Address, Env, Vec};
+
+use crate::extend_record_ttl;
+
+/// Ids held by each history page
+pub(crate) const HISTORY_PAGE_SIZE: u32 = 50;
+
+/// The histories the contract keeps for each user
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum HistoryKey {
+    Rentals(Address), // Rentals taken out as borrower
+    Listings(Address), // Listings created as lender
+}
+
+/// Storage keys of a history's length and its pages
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum HistoryEntryKey {
+    Len(HistoryKey),
+    Page(HistoryKey, u32),
+}
+
+pub(crate) fn len(env: &Env, history: &HistoryKey) -> u32 {
+    env.storage().persistent()
+        .get(&HistoryEntryKey::Len(history.clone()))
+        .unwrap_or(0)
+}
+
+/// Ids on page `page`, oldest first. Every page but the last is full.
+pub(crate) fn page(env: &Env, history: &HistoryKey, page: u32) -> Vec<u64> {
+    env.storage().persistent()
+        .get(&HistoryEntryKey::Page(history.clone(), page))
+        .unwrap_or(Vec::new(env))
+}
+
+/// Append `id`, keeping the newest page alive for at least `ttl` ledgers
+pub(crate) fn push(env: &Env, history: &HistoryKey, id: u64, ttl: u32) {
+    let len = len(env, history);
+    let page_key = HistoryEntryKey::Page(history.clone(), len / HISTORY_PAGE_SIZE);
+
+    let mut ids = page(env, history, len / HISTORY_PAGE_SIZE);
+    ids.push_back(id);
+    env.storage().persistent().set(&page_key, &ids);
+    extend_record_ttl(env, &page_key, ttl);
+
+    let len_key = HistoryEntryKey::Len(history.clone());
+    env.storage().persistent().set(&len_key, &(len + 1));
+    extend_record_ttl(env, &len_key, ttl);
+}
+
+/// Keep the history length and its newest page alive for at least `ttl`
+/// ledgers. Older pages are left to be archived.
+pub(crate) fn bump(env: &Env, history: &HistoryKey, ttl: u32) {
+    let len_key = HistoryEntryKey::Len(history.clone());
+    let Some(len) = env.storage().persistent().get::<_, u32>(&len_key) else {
+        return;
+    };
+    extend_record_ttl(env, &len_key, ttl);
+
+    let page_key = HistoryEntryKey::Page(history.clone(), (len - 1) / HISTORY_PAGE_SIZE);
+    if env.storage().persistent().has(&page_key) {
+        extend_record_ttl(env, &page_key, ttl);
+    }
+}
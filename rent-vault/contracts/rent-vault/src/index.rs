@@ -0,0 +1,252 @@
+//! Record indexes.
+//!
+//! Each index is a set of record ids kept in persistent storage, split into
+//! numbered buckets that are visited in increasing order. Within a bucket, ids
+//! form a doubly linked list in the order they were added, so adding or
+//! removing an id only touches its neighbours however large the index grows.
+//! Indexes that need no grouping keep every id in bucket 0.
+
+use soroban_sdk::{contracttype, Address, Env, Vec};
+
+use crate::{extend_record_ttl, DAY_IN_LEDGERS, RECORD_BUMP_AMOUNT};
+
+/// Hops a cursor may follow back through removed entries before paging
+/// restarts from the beginning
+const MAX_CURSOR_HOPS: u32 = 8;
+
+/// Ledgers a removed entry is remembered for resuming cursors
+const REMOVED_ENTRY_TTL: u32 = DAY_IN_LEDGERS;
+
+/// The indexes the contract maintains
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum IndexKey {
+    OpenListings, // Available listings, in the order they opened
+    LenderListings(Address), // A lender's available listings, in the order they opened
+    LenderRentals(Address), // A lender's active rentals, by rental id
+    BorrowerRentals(Address), // A borrower's active rentals, by rental id
+    ActiveRentals, // All active rentals, bucketed by expiry day
+}
+
+/// Storage keys of an index's buckets and its entries
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum IndexEntryKey {
+    Buckets(IndexKey),
+    Bounds(IndexKey, u64),
+    Node(IndexKey, u64),
+    Removed(IndexKey, u64), // Temporary
+}
+
+/// First and last entry of a bucket (0 when empty)
+#[contracttype]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct IndexBounds {
+    head: u64,
+    tail: u64,
+}
+
+/// Position of an id in an index (0 links to nothing)
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct IndexNode {
+    bucket: u64,
+    prev: u64,
+    next: u64,
+}
+
+/// Where a removed id used to sit, so a cursor left on it can resume
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct RemovedEntry {
+    bucket: u64,
+    prev: u64,
+}
+
+/// Non-empty buckets of an index, in increasing order
+fn load_buckets(env: &Env, index: &IndexKey) -> Vec<u64> {
+    env.storage().persistent()
+        .get(&IndexEntryKey::Buckets(index.clone()))
+        .unwrap_or(Vec::new(env))
+}
+
+fn save_buckets(env: &Env, index: &IndexKey, buckets: &Vec<u64>, ttl: u32) {
+    let key = IndexEntryKey::Buckets(index.clone());
+    if buckets.is_empty() {
+        env.storage().persistent().remove(&key);
+    } else {
+        env.storage().persistent().set(&key, buckets);
+        extend_record_ttl(env, &key, ttl);
+    }
+}
+
+fn load_bounds(env: &Env, index: &IndexKey, bucket: u64) -> IndexBounds {
+    env.storage().persistent()
+        .get(&IndexEntryKey::Bounds(index.clone(), bucket))
+        .unwrap_or_default()
+}
+
+fn save_bounds(env: &Env, index: &IndexKey, bucket: u64, bounds: &IndexBounds, ttl: u32) {
+    let key = IndexEntryKey::Bounds(index.clone(), bucket);
+    env.storage().persistent().set(&key, bounds);
+    extend_record_ttl(env, &key, ttl);
+}
+
+fn load_node(env: &Env, index: &IndexKey, id: u64) -> Option<IndexNode> {
+    env.storage().persistent().get(&IndexEntryKey::Node(index.clone(), id))
+}
+
+fn save_node(env: &Env, index: &IndexKey, id: u64, node: &IndexNode, ttl: u32) {
+    let key = IndexEntryKey::Node(index.clone(), id);
+    env.storage().persistent().set(&key, node);
+    extend_record_ttl(env, &key, ttl);
+}
+
+/// Re-link the neighbour of a node after one of its links changed
+fn set_link(env: &Env, index: &IndexKey, id: u64, prev: Option<u64>, next: Option<u64>) {
+    if let Some(mut node) = load_node(env, index, id) {
+        if let Some(prev) = prev {
+            node.prev = prev;
+        }
+        if let Some(next) = next {
+            node.next = next;
+        }
+        save_node(env, index, id, &node, RECORD_BUMP_AMOUNT);
+    }
+}
+
+/// First id of the first non-empty bucket at or after `bucket`, 0 if none
+fn first_from(env: &Env, index: &IndexKey, bucket: u64) -> u64 {
+    let buckets = load_buckets(env, index);
+    let (Ok(position) | Err(position)) = buckets.binary_search(bucket);
+    match buckets.get(position) {
+        Some(bucket) => load_bounds(env, index, bucket).head,
+        None => 0,
+    }
+}
+
+/// Append `id` to `bucket`, or move it there if it sits in another bucket.
+/// The entry and the bucket live for at least `ttl` ledgers.
+pub(crate) fn insert(env: &Env, index: &IndexKey, id: u64, bucket: u64, ttl: u32) {
+    if let Some(node) = load_node(env, index, id) {
+        if node.bucket == bucket {
+            return;
+        }
+        remove(env, index, id);
+    }
+
+    let mut bounds = load_bounds(env, index, bucket);
+    let prev = bounds.tail;
+
+    save_node(env, index, id, &IndexNode { bucket, prev, next: 0 }, ttl);
+
+    if prev == 0 {
+        bounds.head = id;
+
+        let mut buckets = load_buckets(env, index);
+        if let Err(position) = buckets.binary_search(bucket) {
+            buckets.insert(position, bucket);
+        }
+        save_buckets(env, index, &buckets, ttl);
+    } else {
+        set_link(env, index, prev, None, Some(id));
+    }
+
+    bounds.tail = id;
+    save_bounds(env, index, bucket, &bounds, ttl);
+}
+
+/// Drop `id` from the index. Does nothing if it isn't indexed.
+pub(crate) fn remove(env: &Env, index: &IndexKey, id: u64) {
+    let Some(node) = load_node(env, index, id) else {
+        return;
+    };
+
+    let mut bounds = load_bounds(env, index, node.bucket);
+
+    if node.prev == 0 {
+        bounds.head = node.next;
+    } else {
+        set_link(env, index, node.prev, None, Some(node.next));
+    }
+    if node.next == 0 {
+        bounds.tail = node.prev;
+    } else {
+        set_link(env, index, node.next, Some(node.prev), None);
+    }
+
+    env.storage().persistent().remove(&IndexEntryKey::Node(index.clone(), id));
+
+    if bounds.head == 0 {
+        env.storage().persistent().remove(&IndexEntryKey::Bounds(index.clone(), node.bucket));
+
+        let mut buckets = load_buckets(env, index);
+        if let Ok(position) = buckets.binary_search(node.bucket) {
+            buckets.remove(position);
+        }
+        save_buckets(env, index, &buckets, RECORD_BUMP_AMOUNT);
+    } else {
+        save_bounds(env, index, node.bucket, &bounds, RECORD_BUMP_AMOUNT);
+    }
+
+    // Cursors left on this entry resume after its predecessor
+    let key = IndexEntryKey::Removed(index.clone(), id);
+    let removed = RemovedEntry { bucket: node.bucket, prev: node.prev };
+    env.storage().temporary().set(&key, &removed);
+    env.storage().temporary().extend_ttl(&key, REMOVED_ENTRY_TTL, REMOVED_ENTRY_TTL);
+}
+
+/// Id following `after_id`, or the first id when `after_id` is 0. A cursor
+/// whose entry has since left the index resumes after the entry that preceded
+/// it; once that trail is lost, paging restarts from the first id. Returns 0
+/// at the end of the index.
+pub(crate) fn next_after(env: &Env, index: &IndexKey, after_id: u64) -> u64 {
+    let mut id = after_id;
+    for _ in 0..MAX_CURSOR_HOPS {
+        if id == 0 {
+            break;
+        }
+        if load_node(env, index, id).is_some() {
+            return next(env, index, id);
+        }
+
+        let removed: Option<RemovedEntry> = env.storage().temporary()
+            .get(&IndexEntryKey::Removed(index.clone(), id));
+        let Some(removed) = removed else {
+            break;
+        };
+        if removed.prev == 0 {
+            return first_from(env, index, removed.bucket);
+        }
+        id = removed.prev;
+    }
+
+    first_from(env, index, 0)
+}
+
+/// Id following `id`, which must be indexed. Returns 0 at the end of the index.
+pub(crate) fn next(env: &Env, index: &IndexKey, id: u64) -> u64 {
+    let Some(node) = load_node(env, index, id) else {
+        return 0;
+    };
+    if node.next != 0 {
+        return node.next;
+    }
+
+    match node.bucket.checked_add(1) {
+        Some(bucket) => first_from(env, index, bucket),
+        None => 0,
+    }
+}
+
+/// Keep the entry for `id`, its bucket and the bucket list alive for `ttl`
+/// ledgers
+pub(crate) fn bump(env: &Env, index: &IndexKey, id: u64, ttl: u32) {
+    let Some(node) = load_node(env, index, id) else {
+        return;
+    };
+
+    extend_record_ttl(env, &IndexEntryKey::Node(index.clone(), id), ttl);
+    extend_record_ttl(env, &IndexEntryKey::Bounds(index.clone(), node.bucket), ttl);
+    extend_record_ttl(env, &IndexEntryKey::Buckets(index.clone()), ttl);
+}
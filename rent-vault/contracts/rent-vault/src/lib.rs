@@ -1,11 +1,18 @@
 #![no_std]
+#![allow(clippy::too_many_arguments)]
 use soroban_sdk::{
-    contract, contractimpl, contracttype, token, Address, Env, Symbol, Vec,
-    symbol_short
+    contract, contracterror, contractimpl, contracttype, token, Address, BytesN, Env, IntoVal,
+    Map, Symbol, Val, Vec, symbol_short
 };
 
+use history::HistoryKey;
+use index::IndexKey;
+use migration::MigrationCursor;
+pub use oracle::{Asset, OracleConfig, PriceData};
+pub use pricing::{FeeModel, RateTier};
+
 // Error codes
-#[contracttype]
+#[contracterror]
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
 #[repr(u32)]
 pub enum Error {
@@ -20,6 +27,36 @@ pub enum Error {
     InvalidDuration = 9,
     InsufficientCollateral = 10,
     UnauthorizedReturn = 11,
+    ListingNotFound = 12,
+    ListingNotAvailable = 13,
+    InvalidConfig = 14,
+    NoPendingAdmin = 15,
+    ContractPaused = 16,
+    MigrationPending = 17,
+    MigrationNotNeeded = 18,
+    TokenNotSupported = 19,
+    CollateralNotAccepted = 20,
+    OracleNotConfigured = 21,
+    PriceUnavailable = 22,
+    StalePrice = 23,
+    RentalHealthy = 24,
+    InvalidLatePolicy = 25,
+    InvalidFeeModel = 26,
+    ListingRented = 27,
+    InvalidPageSize = 28,
+}
+
+/// Circuit breaker state. Modes are ordered from least to most restrictive:
+/// `Paused` stops new listings and rentals but still lets users adjust the
+/// positions they hold, `WithdrawOnly` additionally blocks everything that
+/// isn't unwinding an existing position.
+#[contracttype]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum ContractMode {
+    Normal = 0,
+    Paused = 1,
+    WithdrawOnly = 2,
 }
 
 // Data structures
@@ -27,16 +64,48 @@ pub enum Error {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct RentalAgreement {
     pub rental_id: u64,
+    pub listing_id: u64,
     pub lender: Address,
     pub borrower: Address,
     pub token_address: Address,
-    pub amount: i128,
-    pub collateral_amount: i128,
-    pub rental_fee: i128,
+    pub amount: i128, // Principal still outstanding
+    pub amount_returned: i128, // Principal repaid through partial returns
+    pub collateral_token: Address,
+    pub collateral_amount: i128, // Collateral still held
+    pub rental_fee: i128, // Total rental fee paid to the lender, including extensions
+    pub fee_model: FeeModel, // Billing model, with tiers resolved to the rate reached
+    pub rental_rate: i128, // Rate billed (basis points, see `FeeModel`)
+    pub platform_fee_rate: i128, // Platform fee rate when rented (basis points)
+    pub fee_escrowed: bool, // Rental fee is held by the contract and earned over the term
+    pub fees_claimed: i128, // Rental fee paid out to the lender so far
+    pub fee_refunded: i128, // Unearned rental fee returned to the borrower
     pub start_time: u64,
     pub end_time: u64,
-    pub is_active: bool,
-    pub is_completed: bool,
+    pub status: RentalStatus,
+    pub settled_at: u64, // Zero while the rental is active
+    pub penalty_paid: i128, // Collateral forfeited to the lender and liquidator
+    pub collateral_refunded: i128, // Collateral returned to the borrower, including partial returns
+    pub late_policy: LatePolicy, // Listing late policy when rented
+}
+
+/// Lifecycle of a rental. Every rental starts `Active` and moves to exactly
+/// one final state; see `settle_rental`.
+#[contracttype]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u32)]
+pub enum RentalStatus {
+    Active = 0,
+    Returned = 1,
+    ReturnedLate = 2,
+    Defaulted = 3,
+    Liquidated = 4,
+    Cancelled = 5,
+}
+
+impl RentalStatus {
+    fn can_transition_to(self, next: RentalStatus) -> bool {
+        self == RentalStatus::Active && next != RentalStatus::Active
+    }
 }
 
 #[contracttype]
@@ -45,21 +114,135 @@ pub struct TokenListing {
     pub listing_id: u64,
     pub lender: Address,
     pub token_address: Address,
-    pub amount: i128,
-    pub rental_rate: i128, // Fee percentage per day (basis points)
-    pub min_duration: u64, // Minimum rental duration in days
-    pub max_duration: u64, // Maximum rental duration in days
+    pub amount: i128, // Total principal listed
+    pub available_amount: i128, // Principal still in escrow and open for rental
+    pub rented_amount: i128, // Principal currently out with borrowers
+    pub rental_rate: i128, // Fee rate applied by the listing's fee model (basis points)
+    pub min_duration: u64, // Minimum rental duration in seconds
+    pub max_duration: u64, // Maximum rental duration in seconds
     pub collateral_rate: i128, // Collateral percentage required (basis points)
+    pub collateral_tokens: Vec<Address>, // Assets accepted as collateral
+    pub options: ListingOptions,
     pub is_available: bool,
 }
 
+/// Cost of a prospective rental, as `rent_tokens` would charge it
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RentalQuote {
+    pub fee_model: FeeModel, // Model billed; tiered listings settle on `Daily`
+    pub rental_rate: i128, // Rate billed (basis points, see `FeeModel`)
+    pub platform_fee_rate: i128, // Platform fee rate applied (basis points)
+    pub rental_fee: i128, // Paid to the lender
+    pub platform_fee: i128, // Kept by the platform
+    pub total_fee: i128, // Rental and platform fee, in the rented token
+    pub collateral_amount: i128, // Locked in the collateral token
+    pub total_debit: i128, // Taken from the borrower in the rented token, collateral included if it's the same token
+    pub end_time: u64,
+}
+
+/// Settlement of a full return, as `return_tokens` would perform it
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReturnQuote {
+    pub status: RentalStatus,
+    pub penalty: i128, // Collateral forfeited to the lender
+    pub collateral_refund: i128, // Collateral returned to the borrower
+    pub fee_refund: i128, // Unearned escrowed fee returned to the borrower
+    pub lender_payout: i128, // Paid to the lender in the rented token: principal and released fees
+    pub relisted: bool, // Principal goes back into a standing listing instead of to the lender
+}
+
+/// Criteria for browsing open listings. Unset fields match everything.
+#[contracttype]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ListingFilter {
+    pub token: Option<Address>,
+    pub min_amount: Option<i128>, // Compared with the amount still available
+    pub max_amount: Option<i128>,
+    pub max_rate: Option<i128>, // Highest acceptable base rental rate (basis points)
+    pub min_duration: Option<u64>, // Listings must allow some duration within [min_duration, max_duration]
+    pub max_duration: Option<u64>,
+}
+
+/// A page of open listings. Pass `next_cursor` back to continue; 0 means
+/// there is nothing further.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ListingPage {
+    pub listings: Vec<TokenListing>,
+    pub next_cursor: u64,
+}
+
+/// A page of rentals. Pass `next_cursor` back to continue; 0 means there is
+/// nothing further.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RentalPage {
+    pub rentals: Vec<RentalAgreement>,
+    pub next_cursor: u64,
+}
+
+/// Optional listing behaviour chosen by the lender
+#[contracttype]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ListingOptions {
+    pub standing: bool, // Returned principal goes back into escrow and the listing reopens
+    pub expires_at: u64, // Timestamp after which the listing stops accepting rentals (0 = never)
+    pub fixed_term: bool, // Borrowers can't extend rentals of this listing
+    pub escrow_fees: bool, // Rental fees are released pro rata and refunded on early return
+    pub late_policy: LatePolicy,
+    pub fee_model: FeeModel,
+}
+
+/// Collateral forfeited for returning after `end_time`. The penalty grows by
+/// `daily_penalty_rate` for every started day past the grace period, up to
+/// `max_penalty_rate`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LatePolicy {
+    pub grace_period: u64, // Seconds after end_time before penalties start
+    pub daily_penalty_rate: i128, // Collateral forfeited per day late (basis points)
+    pub max_penalty_rate: i128, // Cap on the collateral forfeited (basis points)
+}
+
+impl Default for LatePolicy {
+    /// A flat 10% of collateral as soon as the rental is late
+    fn default() -> Self {
+        LatePolicy {
+            grace_period: 0,
+            daily_penalty_rate: 1000,
+            max_penalty_rate: 1000,
+        }
+    }
+}
+
 #[contracttype]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct PlatformConfig {
     pub admin: Address,
     pub platform_fee_rate: i128, // Platform fee percentage (basis points)
     pub min_collateral_rate: i128, // Minimum collateral percentage (basis points)
-    pub max_rental_duration: u64, // Maximum rental duration in days
+    pub max_rental_duration: u64, // Maximum rental duration in seconds
+    pub default_grace_period: u64, // Seconds after end_time before a rental can be defaulted
+    pub max_late_policy: LatePolicy, // Upper bounds for each field of listing late policies
+}
+
+/// Risk parameters for a token accepted by the platform. Overrides take
+/// precedence over the matching `PlatformConfig` values; unset ones follow
+/// the platform.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TokenConfig {
+    pub token_address: Address,
+    pub min_amount: i128, // Minimum listing amount
+    pub min_collateral_rate: Option<i128>, // Minimum collateral override (basis points)
+    pub max_rental_duration: Option<u64>, // Maximum rental duration override in seconds
+    pub platform_fee_rate: Option<i128>, // Platform fee override (basis points)
+    pub maintenance_collateral_rate: i128, // Health factor below which rentals can be liquidated (basis points)
+    pub liquidation_bonus_rate: i128, // Share of seized collateral paid to the liquidator (basis points)
+    pub price_feed: Option<Address>, // Overrides the platform price feed for this token
+    pub max_price_age: Option<u64>, // Overrides the platform staleness bound, in seconds
 }
 
 // Storage keys
@@ -68,35 +251,692 @@ const NEXT_LISTING_ID: Symbol = symbol_short!("list_id");
 const CONFIG: Symbol = symbol_short!("config");
 const RENTAL: Symbol = symbol_short!("rental");
 const LISTING: Symbol = symbol_short!("listing");
-const USER_RENTALS: Symbol = symbol_short!("u_rents");
-const USER_LISTINGS: Symbol = symbol_short!("u_lists");
+const ACCRUED_FEES: Symbol = symbol_short!("fees");
+const PENDING_ADMIN: Symbol = symbol_short!("p_admin");
+const MODE: Symbol = symbol_short!("mode");
+const PAUSER: Symbol = symbol_short!("pauser");
+const SCHEMA: Symbol = symbol_short!("schema");
+const MIGRATION: Symbol = symbol_short!("migrate");
+const TOKENS: Symbol = symbol_short!("tokens");
+const ORACLE: Symbol = symbol_short!("oracle");
+
+// Storage TTL, in ledgers (~5 seconds each)
+const LEDGER_SECONDS: u64 = 5;
+pub(crate) const DAY_IN_SECONDS: u64 = 24 * 60 * 60;
+const DAY_IN_LEDGERS: u32 = 17280;
+const INSTANCE_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
+const INSTANCE_LIFETIME_THRESHOLD: u32 = INSTANCE_BUMP_AMOUNT - DAY_IN_LEDGERS;
+const RECORD_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS; // Minimum lifetime granted on every touch
+const RENTAL_TTL_MARGIN: u32 = 30 * DAY_IN_LEDGERS; // Kept past end_time for late settlement
+
+// Versioning
+const CONTRACT_VERSION: u32 = 2; // Bumped on every released wasm
+const STORAGE_VERSION: u32 = 2; // Bumped whenever a stored record layout changes
+
+// Configuration bounds
+const MAX_PLATFORM_FEE_RATE: i128 = 2000; // 20% of the rental fee
+const MAX_COLLATERAL_RATE: i128 = 50000; // 500% of the rented amount
+const MAX_RENTAL_DURATION_LIMIT: u64 = 3650 * 24 * 60 * 60; // 10 years
+const MAX_LIQUIDATION_BONUS_RATE: i128 = 2000; // 20% of the seized collateral
+const MAX_DEFAULT_GRACE_PERIOD: u64 = 30 * 24 * 60 * 60; // 30 days
+const MAX_LATE_GRACE_PERIOD: u64 = 30 * 24 * 60 * 60; // 30 days
+const MAX_LATE_PENALTY_RATE: i128 = 10000; // All of the collateral
+
+// Paginated views
+const MAX_PAGE_SIZE: u32 = 50;
+const MAX_INDEX_SCAN: u32 = 200; // Index entries examined per call
+
+fn extend_instance_ttl(env: &Env) {
+    env.storage().instance().extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+}
+
+/// Keep a persistent entry alive for at least `ledgers` more ledgers
+fn extend_record_ttl<K>(env: &Env, key: &K, ledgers: u32)
+where
+    K: IntoVal<Env, Val>,
+{
+    let extend_to = ledgers.max(RECORD_BUMP_AMOUNT).min(env.storage().max_ttl());
+    env.storage().persistent().extend_ttl(key, extend_to.saturating_sub(DAY_IN_LEDGERS), extend_to);
+}
+
+/// Ledgers a record holding `balance` escrowed tokens must survive: as long as
+/// the network allows while any funds are locked in it
+fn escrow_ttl(env: &Env, balance: i128) -> u32 {
+    if balance > 0 {
+        env.storage().max_ttl()
+    } else {
+        RECORD_BUMP_AMOUNT
+    }
+}
+
+/// Ledgers a rental's records must survive: until `end_time` plus a margin
+fn rental_ttl(env: &Env, rental: &RentalAgreement) -> u32 {
+    let remaining = rental.end_time.saturating_sub(env.ledger().timestamp()) / LEDGER_SECONDS;
+    u32::try_from(remaining)
+        .unwrap_or(u32::MAX)
+        .saturating_add(RENTAL_TTL_MARGIN)
+}
+
+/// Load the platform config. Every entrypoint that changes state goes
+/// through here, which also keeps the contract instance alive.
+fn load_config(env: &Env) -> Result<PlatformConfig, Error> {
+    extend_instance_ttl(env);
+    env.storage().instance()
+        .get(&CONFIG)
+        .ok_or(Error::NotInitialized)
+}
+
+fn validate_config(config: &PlatformConfig) -> Result<(), Error> {
+    if config.platform_fee_rate < 0 || config.platform_fee_rate > MAX_PLATFORM_FEE_RATE {
+        return Err(Error::InvalidConfig);
+    }
+
+    if config.min_collateral_rate <= 0 || config.min_collateral_rate > MAX_COLLATERAL_RATE {
+        return Err(Error::InvalidConfig);
+    }
+
+    if config.max_rental_duration == 0 || config.max_rental_duration > MAX_RENTAL_DURATION_LIMIT {
+        return Err(Error::InvalidConfig);
+    }
+
+    if config.default_grace_period > MAX_DEFAULT_GRACE_PERIOD {
+        return Err(Error::InvalidConfig);
+    }
+
+    let max_late_policy = &config.max_late_policy;
+    if max_late_policy.grace_period > MAX_LATE_GRACE_PERIOD
+        || max_late_policy.daily_penalty_rate < 0
+        || max_late_policy.daily_penalty_rate > MAX_LATE_PENALTY_RATE
+        || max_late_policy.max_penalty_rate < 0
+        || max_late_policy.max_penalty_rate > MAX_LATE_PENALTY_RATE
+    {
+        return Err(Error::InvalidConfig);
+    }
+
+    Ok(())
+}
+
+/// Check a listing's late policy against the platform bounds
+fn validate_late_policy(policy: &LatePolicy, config: &PlatformConfig) -> Result<(), Error> {
+    let limits = &config.max_late_policy;
+    if policy.grace_period > limits.grace_period
+        || policy.daily_penalty_rate < 0
+        || policy.daily_penalty_rate > limits.daily_penalty_rate
+        || policy.max_penalty_rate < 0
+        || policy.max_penalty_rate > limits.max_penalty_rate
+    {
+        return Err(Error::InvalidLatePolicy);
+    }
+
+    Ok(())
+}
+
+/// Check a listing's pricing and duration terms against the token limits
+fn validate_listing_terms(
+    config: &PlatformConfig,
+    token_config: &TokenConfig,
+    rental_rate: i128,
+    min_duration: u64,
+    max_duration: u64,
+    collateral_rate: i128,
+    fee_model: &FeeModel,
+) -> Result<(), Error> {
+    if collateral_rate < min_collateral_rate(config, token_config) {
+        return Err(Error::InsufficientCollateral);
+    }
+
+    if min_duration > max_duration
+        || max_duration > max_rental_duration(config, token_config)
+    {
+        return Err(Error::InvalidDuration);
+    }
+
+    pricing::validate_fee_model(fee_model, rental_rate)
+}
+
+/// Check a token's parameters, resolving unset overrides against `config`
+fn validate_token_config(token_config: &TokenConfig, config: &PlatformConfig) -> Result<(), Error> {
+    if token_config.min_amount <= 0 {
+        return Err(Error::InvalidConfig);
+    }
+
+    if let Some(rate) = token_config.min_collateral_rate {
+        if rate <= 0 || rate > MAX_COLLATERAL_RATE {
+            return Err(Error::InvalidConfig);
+        }
+    }
+
+    if let Some(duration) = token_config.max_rental_duration {
+        if duration == 0 || duration > MAX_RENTAL_DURATION_LIMIT {
+            return Err(Error::InvalidConfig);
+        }
+    }
+
+    // Maintenance must sit below the opening requirement, or fresh rentals
+    // would be liquidatable right away
+    if token_config.maintenance_collateral_rate <= 0
+        || token_config.maintenance_collateral_rate > min_collateral_rate(config, token_config)
+    {
+        return Err(Error::InvalidConfig);
+    }
+
+    if !(0..=MAX_LIQUIDATION_BONUS_RATE).contains(&token_config.liquidation_bonus_rate) {
+        return Err(Error::InvalidConfig);
+    }
+
+    if let Some(fee_rate) = token_config.platform_fee_rate {
+        if !(0..=MAX_PLATFORM_FEE_RATE).contains(&fee_rate) {
+            return Err(Error::InvalidConfig);
+        }
+    }
+
+    if token_config.max_price_age == Some(0) {
+        return Err(Error::InvalidConfig);
+    }
+
+    Ok(())
+}
+
+/// Validate and store a config changed by the admin. Tokens that follow the
+/// platform values must remain valid under the new ones.
+fn save_config(env: &Env, config: &PlatformConfig) -> Result<(), Error> {
+    validate_config(config)?;
+    for token_config in supported_tokens(env).values() {
+        validate_token_config(&token_config, config)?;
+    }
+    env.storage().instance().set(&CONFIG, config);
+    Ok(())
+}
+
+fn current_mode(env: &Env) -> ContractMode {
+    env.storage().instance()
+        .get(&MODE)
+        .unwrap_or(ContractMode::Normal)
+}
+
+/// Fail unless the contract is at most as restricted as `max_mode`
+fn require_mode(env: &Env, max_mode: ContractMode) -> Result<(), Error> {
+    if current_mode(env) > max_mode {
+        return Err(Error::ContractPaused);
+    }
+    Ok(())
+}
+
+/// Storage schema in use; deployments predating the version key are at 1
+fn storage_version(env: &Env) -> u32 {
+    env.storage().instance()
+        .get(&SCHEMA)
+        .unwrap_or(1)
+}
+
+/// Records can't be decoded until an upgrade's migration has completed
+fn require_current_schema(env: &Env) -> Result<(), Error> {
+    if storage_version(env) < STORAGE_VERSION {
+        return Err(Error::MigrationPending);
+    }
+    Ok(())
+}
+
+fn supported_tokens(env: &Env) -> Map<Address, TokenConfig> {
+    env.storage().instance()
+        .get(&TOKENS)
+        .unwrap_or(Map::new(env))
+}
+
+fn load_token_config(env: &Env, token_address: &Address) -> Result<TokenConfig, Error> {
+    supported_tokens(env)
+        .get(token_address.clone())
+        .ok_or(Error::TokenNotSupported)
+}
+
+/// Platform fee rate charged on rentals of a token
+fn platform_fee_rate(config: &PlatformConfig, token_config: &TokenConfig) -> i128 {
+    token_config.platform_fee_rate.unwrap_or(config.platform_fee_rate)
+}
+
+/// Minimum collateral rate for listings of a token
+fn min_collateral_rate(config: &PlatformConfig, token_config: &TokenConfig) -> i128 {
+    token_config.min_collateral_rate.unwrap_or(config.min_collateral_rate)
+}
+
+/// Maximum rental duration for a token, in seconds
+fn max_rental_duration(config: &PlatformConfig, token_config: &TokenConfig) -> u64 {
+    token_config.max_rental_duration.unwrap_or(config.max_rental_duration)
+}
+
+fn load_listing(env: &Env, listing_id: u64) -> Result<TokenListing, Error> {
+    require_current_schema(env)?;
+    env.storage().persistent()
+        .get(&(LISTING, listing_id))
+        .ok_or(Error::ListingNotFound)
+}
+
+fn load_rental(env: &Env, rental_id: u64) -> Result<RentalAgreement, Error> {
+    require_current_schema(env)?;
+    env.storage().persistent()
+        .get(&(RENTAL, rental_id))
+        .ok_or(Error::RentalNotFound)
+}
+
+fn require_active(rental: &RentalAgreement) -> Result<(), Error> {
+    if rental.status != RentalStatus::Active {
+        return Err(Error::RentalExpired);
+    }
+    Ok(())
+}
+
+/// Move a rental to its final state and record how the collateral was split
+fn settle_rental(
+    env: &Env,
+    rental: &mut RentalAgreement,
+    status: RentalStatus,
+    penalty_paid: i128,
+    collateral_refunded: i128,
+) -> Result<(), Error> {
+    if !rental.status.can_transition_to(status) {
+        return Err(Error::RentalExpired);
+    }
+
+    rental.status = status;
+    rental.settled_at = env.ledger().timestamp();
+    rental.penalty_paid = penalty_paid;
+    rental.collateral_refunded += collateral_refunded;
+    save_rental(env, rental);
+
+    Ok(())
+}
+
+fn listing_expired(listing: &TokenListing, now: u64) -> bool {
+    listing.options.expires_at != 0 && now >= listing.options.expires_at
+}
+
+/// Whether principal returned to `listing` should reopen it. Lenders are paid
+/// directly once the listing expires or the contract is winding down.
+fn relists(env: &Env, listing: &TokenListing, now: u64) -> bool {
+    listing.options.standing
+        && !listing_expired(listing, now)
+        && current_mode(env) != ContractMode::WithdrawOnly
+}
+
+/// The listing principal returned at `now` goes back into, if any
+fn relisting_target(env: &Env, rental: &RentalAgreement, now: u64) -> Option<TokenListing> {
+    load_listing(env, rental.listing_id)
+        .ok()
+        .filter(|listing| relists(env, listing, now))
+}
+
+/// Validate a rental of `amount` from `listing` for `duration` seconds and
+/// work out what it costs. `rent_tokens` executes exactly this quote.
+fn rental_quote(
+    env: &Env,
+    listing: &TokenListing,
+    amount: i128,
+    duration: u64,
+    collateral_token: &Address,
+) -> Result<RentalQuote, Error> {
+    require_mode(env, ContractMode::Normal)?;
+
+    let now = env.ledger().timestamp();
+    if !listing.is_available || listing_expired(listing, now) {
+        return Err(Error::ListingNotAvailable);
+    }
+
+    if amount <= 0 {
+        return Err(Error::InvalidAmount);
+    }
+
+    if amount > listing.available_amount {
+        return Err(Error::InsufficientBalance);
+    }
+
+    if duration == 0 || duration < listing.min_duration || duration > listing.max_duration {
+        return Err(Error::InvalidDuration);
+    }
+
+    let config = load_config(env)?;
+    let token_config = load_token_config(env, &listing.token_address)?;
+
+    if !listing.collateral_tokens.contains(collateral_token) {
+        return Err(Error::CollateralNotAccepted);
+    }
+    load_token_config(env, collateral_token)?;
+
+    // Smaller fills than the token minimum would leave dust rentals, unless
+    // they drain what's left of the listing
+    if amount < token_config.min_amount && amount != listing.available_amount {
+        return Err(Error::InvalidAmount);
+    }
+
+    // Fees and collateral are charged on the portion taken
+    let platform_fee_rate = platform_fee_rate(&config, &token_config);
+    let fees = pricing::quote(
+        &listing.options.fee_model,
+        listing.rental_rate,
+        amount,
+        duration,
+        platform_fee_rate,
+    );
+    let collateral_amount = oracle::required_collateral(
+        env,
+        &listing.token_address,
+        amount,
+        collateral_token,
+        listing.collateral_rate,
+    )?;
+
+    let total_debit = if *collateral_token == listing.token_address {
+        fees.total_fee + collateral_amount
+    } else {
+        fees.total_fee
+    };
+
+    Ok(RentalQuote {
+        fee_model: fees.fee_model,
+        rental_rate: fees.rental_rate,
+        platform_fee_rate,
+        rental_fee: fees.rental_fee,
+        platform_fee: fees.platform_fee,
+        total_fee: fees.total_fee,
+        collateral_amount,
+        total_debit,
+        end_time: now + duration,
+    })
+}
+
+/// Work out the settlement of `rental` if it were returned in full at `now`.
+/// `return_tokens` executes exactly this quote.
+fn return_quote(env: &Env, rental: &RentalAgreement, now: u64) -> Result<ReturnQuote, Error> {
+    require_active(rental)?;
+
+    // On-time return: full collateral back. Late return: collateral forfeit per the late policy
+    let status = if now <= rental.end_time {
+        RentalStatus::Returned
+    } else {
+        RentalStatus::ReturnedLate
+    };
+    let penalty = late_penalty(rental, now);
+
+    // Escrowed fees: the lender keeps what was earned, the borrower gets the rest back
+    let earned = earned_fee(rental, now);
+    let fee_payout = (earned - rental.fees_claimed).max(0);
+
+    let relisted = relisting_target(env, rental, now).is_some();
+    let principal_payout = if relisted { 0 } else { rental.amount };
+
+    Ok(ReturnQuote {
+        status,
+        penalty,
+        collateral_refund: rental.collateral_amount - penalty,
+        fee_refund: rental.rental_fee - earned,
+        lender_payout: principal_payout + fee_payout,
+        relisted,
+    })
+}
+
+/// Collateral forfeited if the rental is returned at `now`
+fn late_penalty(rental: &RentalAgreement, now: u64) -> i128 {
+    let policy = &rental.late_policy;
+    let penalty_start = rental.end_time.saturating_add(policy.grace_period);
+    if now <= penalty_start {
+        return 0;
+    }
+
+    let days_late = (now - penalty_start).div_ceil(DAY_IN_SECONDS) as i128;
+    let penalty_rate = days_late
+        .saturating_mul(policy.daily_penalty_rate)
+        .min(policy.max_penalty_rate);
+    (rental.collateral_amount * penalty_rate) / 10000
+}
+
+/// Share of the rental fee the lender has earned by `now`. Fees paid up front
+/// count as earned immediately; escrowed fees accrue linearly over the term.
+/// Never less than what was already claimed, as an extension can lower the
+/// accrual rate of a fee billed by started day.
+fn earned_fee(rental: &RentalAgreement, now: u64) -> i128 {
+    let term = rental.end_time.saturating_sub(rental.start_time);
+    if !rental.fee_escrowed || term == 0 || now >= rental.end_time {
+        return rental.rental_fee;
+    }
+
+    let elapsed = now.saturating_sub(rental.start_time);
+    let accrued = rental.rental_fee * elapsed as i128 / term as i128;
+    accrued.max(rental.fees_claimed)
+}
+
+/// Pay the lender the part of `earned` not claimed yet. Returns the amount paid.
+fn release_fees(env: &Env, rental: &mut RentalAgreement, earned: i128) -> i128 {
+    let payout = earned - rental.fees_claimed;
+    if payout <= 0 {
+        return 0;
+    }
+
+    let token_client = token::Client::new(env, &rental.token_address);
+    token_client.transfer(&env.current_contract_address(), &rental.lender, &payout);
+    rental.fees_claimed = earned;
+    payout
+}
+
+/// Move returned principal from the borrower. Standing listings take it back
+/// into escrow and reopen; otherwise it goes straight to the lender.
+fn repay_principal(env: &Env, rental: &RentalAgreement, amount: i128) {
+    let token_client = token::Client::new(env, &rental.token_address);
+
+    // Legacy rentals aren't linked to a listing
+    let Ok(mut listing) = load_listing(env, rental.listing_id) else {
+        token_client.transfer(&rental.borrower, &rental.lender, &amount);
+        return;
+    };
+    listing.rented_amount -= amount;
+
+    if !relists(env, &listing, env.ledger().timestamp()) {
+        token_client.transfer(&rental.borrower, &rental.lender, &amount);
+        save_listing(env, &listing);
+        return;
+    }
+
+    token_client.transfer(&rental.borrower, &env.current_contract_address(), &amount);
+    listing.available_amount += amount;
+    listing.is_available = true;
+    save_listing(env, &listing);
+
+    env.events().publish(
+        (symbol_short!("relist"), listing.listing_id),
+        (rental.rental_id, amount)
+    );
+}
+
+/// Release a listing from the principal of a rental that will never be
+/// repaid, once it defaults or is liquidated
+fn write_off_principal(env: &Env, rental: &RentalAgreement) {
+    if let Ok(mut listing) = load_listing(env, rental.listing_id) {
+        listing.rented_amount -= rental.amount;
+        save_listing(env, &listing);
+    }
+}
+
+/// Whether an open listing matches every criterion set in `filter`
+fn matches_filter(listing: &TokenListing, filter: &ListingFilter, now: u64) -> bool {
+    if listing_expired(listing, now) {
+        return false;
+    }
+
+    filter.token.as_ref().is_none_or(|token| *token == listing.token_address)
+        && filter.min_amount.is_none_or(|min| listing.available_amount >= min)
+        && filter.max_amount.is_none_or(|max| listing.available_amount <= max)
+        && filter.max_rate.is_none_or(|max| listing.rental_rate <= max)
+        && filter.min_duration.is_none_or(|min| listing.max_duration >= min)
+        && filter.max_duration.is_none_or(|max| listing.min_duration <= max)
+}
+
+/// Store a listing and keep the open-listing indexes in step with it
+fn save_listing(env: &Env, listing: &TokenListing) {
+    let key = (LISTING, listing.listing_id);
+    let ttl = escrow_ttl(env, listing.available_amount);
+    env.storage().persistent().set(&key, listing);
+    extend_record_ttl(env, &key, ttl);
+
+    let id = listing.listing_id;
+    let lender_index = IndexKey::LenderListings(listing.lender.clone());
+    if listing.is_available {
+        index::insert(env, &IndexKey::OpenListings, id, 0, ttl);
+        index::insert(env, &lender_index, id, 0, ttl);
+    } else {
+        index::remove(env, &IndexKey::OpenListings, id);
+        index::remove(env, &lender_index, id);
+    }
+}
+
+/// Bucket of the expiry index a rental ending at `end_time` belongs to
+fn expiry_day(end_time: u64) -> u64 {
+    end_time / DAY_IN_SECONDS
+}
+
+/// Store a rental and keep the active-rental indexes in step with it
+fn save_rental(env: &Env, rental: &RentalAgreement) {
+    let key = (RENTAL, rental.rental_id);
+    let ttl = rental_ttl(env, rental);
+    env.storage().persistent().set(&key, rental);
+    extend_record_ttl(env, &key, ttl);
+
+    let id = rental.rental_id;
+    let lender_index = IndexKey::LenderRentals(rental.lender.clone());
+    let borrower_index = IndexKey::BorrowerRentals(rental.borrower.clone());
+    if rental.status == RentalStatus::Active {
+        index::insert(env, &lender_index, id, 0, ttl);
+        index::insert(env, &borrower_index, id, 0, ttl);
+        index::insert(env, &IndexKey::ActiveRentals, id, expiry_day(rental.end_time), ttl);
+    } else {
+        index::remove(env, &lender_index, id);
+        index::remove(env, &borrower_index, id);
+        index::remove(env, &IndexKey::ActiveRentals, id);
+    }
+}
+
+/// Collect up to `limit` listings from `index` that match `filter`, starting
+/// after listing `cursor`. An empty page can't be told apart from the end of
+/// the index, so `limit` must be at least 1.
+fn listing_page(
+    env: &Env,
+    index: &IndexKey,
+    cursor: u64,
+    limit: u32,
+    filter: &ListingFilter,
+) -> Result<ListingPage, Error> {
+    if limit == 0 {
+        return Err(Error::InvalidPageSize);
+    }
+
+    let now = env.ledger().timestamp();
+    let limit = limit.min(MAX_PAGE_SIZE);
+
+    let mut listings = Vec::new(env);
+    let mut last = cursor;
+    let mut scanned = 0;
+    let mut id = index::next_after(env, index, cursor);
+    while id != 0 && listings.len() < limit && scanned < MAX_INDEX_SCAN {
+        let listing: Option<TokenListing> = env.storage().persistent().get(&(LISTING, id));
+        if let Some(listing) = listing.filter(|listing| matches_filter(listing, filter, now)) {
+            listings.push_back(listing);
+        }
+        last = id;
+        scanned += 1;
+        id = index::next(env, index, id);
+    }
+
+    Ok(ListingPage {
+        listings,
+        next_cursor: if id == 0 { 0 } else { last },
+    })
+}
+
+/// Collect up to `limit` rentals from `index` that end before `ends_before`,
+/// starting after rental `cursor`. Paging stops at the first rental whose
+/// expiry day is past that of `ends_before`, so it suits indexes bucketed by
+/// expiry day as well as unbucketed ones. `limit` must be at least 1.
+fn rental_page(
+    env: &Env,
+    index: &IndexKey,
+    cursor: u64,
+    limit: u32,
+    ends_before: u64,
+) -> Result<RentalPage, Error> {
+    if limit == 0 {
+        return Err(Error::InvalidPageSize);
+    }
+
+    let limit = limit.min(MAX_PAGE_SIZE);
+
+    let mut rentals = Vec::new(env);
+    let mut last = cursor;
+    let mut scanned = 0;
+    let mut id = index::next_after(env, index, cursor);
+    while id != 0 && rentals.len() < limit && scanned < MAX_INDEX_SCAN {
+        let rental: Option<RentalAgreement> = env.storage().persistent().get(&(RENTAL, id));
+        if let Some(rental) = rental {
+            if expiry_day(rental.end_time) > expiry_day(ends_before) {
+                id = 0;
+                break;
+            }
+            if rental.end_time < ends_before {
+                rentals.push_back(rental);
+            }
+        }
+        last = id;
+        scanned += 1;
+        id = index::next(env, index, id);
+    }
+
+    Ok(RentalPage {
+        rentals,
+        next_cursor: if id == 0 { 0 } else { last },
+    })
+}
+
+fn accrued_fees(env: &Env, token_address: &Address) -> i128 {
+    env.storage().persistent()
+        .get(&(ACCRUED_FEES, token_address))
+        .unwrap_or(0)
+}
+
+/// Record platform fees retained by the contract for `token_address`
+fn accrue_fees(env: &Env, token_address: &Address, rental_id: u64, amount: i128) {
+    if amount <= 0 {
+        return;
+    }
+
+    let total = accrued_fees(env, token_address) + amount;
+    env.storage().persistent().set(&(ACCRUED_FEES, token_address), &total);
+    extend_record_ttl(env, &(ACCRUED_FEES, token_address), escrow_ttl(env, total));
+
+    env.events().publish(
+        (symbol_short!("fee_acc"), token_address.clone()),
+        (rental_id, amount, total)
+    );
+}
 
 #[contract]
 pub struct XRentContract;
 
 #[contractimpl]
 impl XRentContract {
-    /// Initialize the contract with admin address
-    pub fn initialize(env: Env, admin: Address) {
-        if env.storage().instance().has(&CONFIG) {
-            panic!("Already initialized");
-        }
-
-        let config = PlatformConfig {
-            admin: admin.clone(),
-            platform_fee_rate: 250, // 2.5% platform fee
-            min_collateral_rate: 1000, // 10% minimum collateral
-            max_rental_duration: 365, // 1 year max rental
-        };
+    /// Initialize the contract at deployment with the full platform config.
+    /// Runs atomically with contract creation, so it can't be front-run.
+    pub fn __constructor(env: Env, config: PlatformConfig) -> Result<(), Error> {
+        config.admin.require_auth();
+        validate_config(&config)?;
 
         env.storage().instance().set(&CONFIG, &config);
         env.storage().instance().set(&NEXT_RENTAL_ID, &1u64);
         env.storage().instance().set(&NEXT_LISTING_ID, &1u64);
+        env.storage().instance().set(&SCHEMA, &STORAGE_VERSION);
+        extend_instance_ttl(&env);
 
         env.events().publish(
             (symbol_short!("init"),),
-            (admin,)
+            (config.admin, config.platform_fee_rate, config.min_collateral_rate, config.max_rental_duration)
         );
+
+        Ok(())
     }
 
     /// Create a new token listing for rental
@@ -109,23 +949,41 @@ impl XRentContract {
         min_duration: u64,
         max_duration: u64,
         collateral_rate: i128,
-    ) -> u64 {
+        collateral_tokens: Vec<Address>,
+        options: ListingOptions,
+    ) -> Result<u64, Error> {
         lender.require_auth();
+        require_mode(&env, ContractMode::Normal)?;
+        require_current_schema(&env)?;
 
-        if amount <= 0 {
-            panic!("Invalid amount");
+        let config = load_config(&env)?;
+        let token_config = load_token_config(&env, &token_address)?;
+
+        if amount <= 0 || amount < token_config.min_amount {
+            return Err(Error::InvalidAmount);
         }
 
-        let config: PlatformConfig = env.storage().instance()
-            .get(&CONFIG)
-            .unwrap_or_else(|| panic!("Not initialized"));
+        validate_listing_terms(
+            &config,
+            &token_config,
+            rental_rate,
+            min_duration,
+            max_duration,
+            collateral_rate,
+            &options.fee_model,
+        )?;
 
-        if collateral_rate < config.min_collateral_rate {
-            panic!("Insufficient collateral");
+        if options.expires_at != 0 && options.expires_at <= env.ledger().timestamp() {
+            return Err(Error::InvalidDuration);
         }
 
-        if max_duration > config.max_rental_duration {
-            panic!("Invalid duration");
+        validate_late_policy(&options.late_policy, &config)?;
+
+        if collateral_tokens.is_empty() {
+            return Err(Error::CollateralNotAccepted);
+        }
+        for collateral_token in collateral_tokens.iter() {
+            load_token_config(&env, &collateral_token)?;
         }
 
         // Transfer tokens to contract for escrow
@@ -141,29 +999,30 @@ impl XRentContract {
             lender: lender.clone(),
             token_address: token_address.clone(),
             amount,
+            available_amount: amount,
+            rented_amount: 0,
             rental_rate,
             min_duration,
             max_duration,
             collateral_rate,
+            collateral_tokens,
+            options,
             is_available: true,
         };
 
-        env.storage().persistent().set(&(LISTING, listing_id), &listing);
+        save_listing(&env, &listing);
         env.storage().instance().set(&NEXT_LISTING_ID, &(listing_id + 1));
 
         // Update user listings
-        let mut user_listings: Vec<u64> = env.storage().persistent()
-            .get(&(USER_LISTINGS, &lender))
-            .unwrap_or(Vec::new(&env));
-        user_listings.push_back(listing_id);
-        env.storage().persistent().set(&(USER_LISTINGS, &lender), &user_listings);
+        let history = HistoryKey::Listings(lender.clone());
+        history::push(&env, &history, listing_id, RECORD_BUMP_AMOUNT);
 
         env.events().publish(
             (symbol_short!("list_tok"), listing_id),
             (lender, token_address, amount, rental_rate)
         );
 
-        listing_id
+        Ok(listing_id)
     }
 
     /// Rent tokens from a listing
@@ -171,176 +1030,363 @@ impl XRentContract {
         env: Env,
         borrower: Address,
         listing_id: u64,
-        duration: u64, // Duration in days
-    ) -> u64 {
+        amount: i128, // Principal to take from the listing
+        duration: u64, // Duration in seconds
+        collateral_token: Address,
+    ) -> Result<u64, Error> {
         borrower.require_auth();
 
-        let mut listing: TokenListing = env.storage().persistent()
-            .get(&(LISTING, listing_id))
-            .unwrap_or_else(|| panic!("Rental not found"));
-
-        if !listing.is_available {
-            panic!("Rental not available");
-        }
-
-        if duration < listing.min_duration || duration > listing.max_duration {
-            panic!("Invalid duration");
-        }
-
-        let config: PlatformConfig = env.storage().instance()
-            .get(&CONFIG)
-            .unwrap_or_else(|| panic!("Not initialized"));
-
-        // Calculate fees and collateral
-        let rental_fee = (listing.amount * listing.rental_rate * duration as i128) / 10000;
-        let platform_fee = (rental_fee * config.platform_fee_rate) / 10000;
-        let total_fee = rental_fee + platform_fee;
-        let collateral_amount = (listing.amount * listing.collateral_rate) / 10000;
+        let mut listing = load_listing(&env, listing_id)?;
+        let quote = rental_quote(&env, &listing, amount, duration, &collateral_token)?;
+        let (rental_fee, platform_fee) = (quote.rental_fee, quote.platform_fee);
+        let collateral_amount = quote.collateral_amount;
 
         // Transfer rental fee and collateral from borrower
         let token_client = token::Client::new(&env, &listing.token_address);
-        token_client.transfer(&borrower, &env.current_contract_address(), &(total_fee + collateral_amount));
+        token_client.transfer(&borrower, &env.current_contract_address(), &quote.total_fee);
 
-        // Transfer rental fee to lender (minus platform fee)
-        token_client.transfer(&env.current_contract_address(), &listing.lender, &rental_fee);
+        let collateral_client = token::Client::new(&env, &collateral_token);
+        collateral_client.transfer(&borrower, &env.current_contract_address(), &collateral_amount);
+
+        // Transfer rental fee to lender (minus platform fee), unless it's escrowed
+        let fee_escrowed = listing.options.escrow_fees;
+        if !fee_escrowed {
+            token_client.transfer(&env.current_contract_address(), &listing.lender, &rental_fee);
+        }
 
         // Transfer tokens to borrower
-        token_client.transfer(&env.current_contract_address(), &borrower, &listing.amount);
+        token_client.transfer(&env.current_contract_address(), &borrower, &amount);
 
         let rental_id: u64 = env.storage().instance()
             .get(&NEXT_RENTAL_ID)
             .unwrap_or(1);
 
-        let current_time = env.ledger().timestamp();
-        let end_time = current_time + (duration * 24 * 60 * 60); // Convert days to seconds
+        // Platform fee stays in the contract treasury
+        accrue_fees(&env, &listing.token_address, rental_id, platform_fee);
 
         let rental = RentalAgreement {
             rental_id,
+            listing_id,
             lender: listing.lender.clone(),
             borrower: borrower.clone(),
             token_address: listing.token_address.clone(),
-            amount: listing.amount,
+            amount,
+            amount_returned: 0,
+            collateral_token,
             collateral_amount,
             rental_fee,
-            start_time: current_time,
-            end_time,
-            is_active: true,
-            is_completed: false,
+            fee_model: quote.fee_model.clone(),
+            rental_rate: quote.rental_rate,
+            platform_fee_rate: quote.platform_fee_rate,
+            fee_escrowed,
+            fees_claimed: if fee_escrowed { 0 } else { rental_fee },
+            fee_refunded: 0,
+            start_time: env.ledger().timestamp(),
+            end_time: quote.end_time,
+            status: RentalStatus::Active,
+            settled_at: 0,
+            penalty_paid: 0,
+            collateral_refunded: 0,
+            late_policy: listing.options.late_policy.clone(),
         };
 
-        env.storage().persistent().set(&(RENTAL, rental_id), &rental);
+        save_rental(&env, &rental);
         env.storage().instance().set(&NEXT_RENTAL_ID, &(rental_id + 1));
+        let ttl = rental_ttl(&env, &rental);
 
         // Update user rentals
-        let mut user_rentals: Vec<u64> = env.storage().persistent()
-            .get(&(USER_RENTALS, &borrower))
-            .unwrap_or(Vec::new(&env));
-        user_rentals.push_back(rental_id);
-        env.storage().persistent().set(&(USER_RENTALS, &borrower), &user_rentals);
+        history::push(&env, &HistoryKey::Rentals(borrower.clone()), rental_id, ttl);
 
-        // Mark listing as unavailable
-        listing.is_available = false;
-        env.storage().persistent().set(&(LISTING, listing_id), &listing);
+        // The listing stays open until fully drawn
+        listing.available_amount -= amount;
+        listing.rented_amount += amount;
+        listing.is_available = listing.available_amount > 0;
+        save_listing(&env, &listing);
+        extend_record_ttl(&env, &(LISTING, listing_id), ttl);
 
         env.events().publish(
             (symbol_short!("rent_tok"), rental_id),
-            (borrower, listing.lender, listing.amount, duration)
+            (borrower, listing.lender, amount, duration)
         );
 
-        rental_id
+        Ok(rental_id)
     }
 
-    /// Return rented tokens
-    pub fn return_tokens(env: Env, borrower: Address, rental_id: u64) {
+    /// Extend an active rental before it expires, paying for the extra days at
+    /// the rates the rental was taken out at
+    pub fn extend_rental(
+        env: Env,
+        borrower: Address,
+        rental_id: u64,
+        extra_duration: u64, // Seconds added to the rental
+    ) -> Result<(), Error> {
         borrower.require_auth();
+        require_mode(&env, ContractMode::Paused)?;
 
-        let mut rental: RentalAgreement = env.storage().persistent()
-            .get(&(RENTAL, rental_id))
-            .unwrap_or_else(|| panic!("Rental not found"));
+        let mut rental = load_rental(&env, rental_id)?;
 
         if rental.borrower != borrower {
-            panic!("Unauthorized return");
+            return Err(Error::UnauthorizedReturn);
         }
 
-        if !rental.is_active {
-            panic!("Rental expired");
-        }
+        require_active(&rental)?;
 
         let current_time = env.ledger().timestamp();
-        let token_client = token::Client::new(&env, &rental.token_address);
+        if current_time > rental.end_time {
+            return Err(Error::RentalExpired);
+        }
 
-        // Transfer tokens back from borrower to lender
-        token_client.transfer(&borrower, &rental.lender, &rental.amount);
+        let listing = load_listing(&env, rental.listing_id)?;
+        if listing.options.fixed_term {
+            return Err(Error::ListingNotAvailable);
+        }
 
-        // Return collateral to borrower
-        if current_time <= rental.end_time {
-            // On-time return: full collateral back
-            token_client.transfer(&env.current_contract_address(), &borrower, &rental.collateral_amount);
-        } else {
-            // Late return: partial collateral forfeit
-            let late_penalty_rate = 1000; // 10% penalty
-            let penalty = (rental.collateral_amount * late_penalty_rate) / 10000;
-            let refund = rental.collateral_amount - penalty;
-            
-            if refund > 0 {
-                token_client.transfer(&env.current_contract_address(), &borrower, &refund);
-            }
-            
-            // Send penalty to lender as compensation
-            if penalty > 0 {
-                token_client.transfer(&env.current_contract_address(), &rental.lender, &penalty);
-            }
+        let config = load_config(&env)?;
+        let token_config = load_token_config(&env, &rental.token_address)?;
+        let duration = rental.end_time - rental.start_time;
+        let total_duration = duration
+            .checked_add(extra_duration)
+            .ok_or(Error::InvalidDuration)?;
+        if extra_duration == 0
+            || total_duration > listing.max_duration
+            || total_duration > max_rental_duration(&config, &token_config)
+        {
+            return Err(Error::InvalidDuration);
+        }
+
+        let quote = pricing::extension_quote(
+            &rental.fee_model,
+            rental.rental_rate,
+            rental.amount,
+            duration,
+            extra_duration,
+            rental.platform_fee_rate,
+        );
+        let (rental_fee, platform_fee) = (quote.rental_fee, quote.platform_fee);
+
+        let token_client = token::Client::new(&env, &rental.token_address);
+        token_client.transfer(&borrower, &env.current_contract_address(), &quote.total_fee);
+        if !rental.fee_escrowed {
+            token_client.transfer(&env.current_contract_address(), &rental.lender, &rental_fee);
+            rental.fees_claimed += rental_fee;
         }
+        accrue_fees(&env, &rental.token_address, rental_id, platform_fee);
 
-        // Mark rental as completed
-        rental.is_active = false;
-        rental.is_completed = true;
-        env.storage().persistent().set(&(RENTAL, rental_id), &rental);
+        rental.rental_fee += rental_fee;
+        rental.end_time += extra_duration;
+        save_rental(&env, &rental);
+        extend_record_ttl(&env, &(LISTING, rental.listing_id), rental_ttl(&env, &rental));
 
         env.events().publish(
-            (symbol_short!("ret_tok"), rental_id),
-            (borrower, rental.lender, current_time <= rental.end_time)
+            (symbol_short!("extend"), rental_id),
+            (borrower, extra_duration, rental.end_time)
         );
+
+        Ok(())
     }
 
-    /// Emergency return by lender (after expiry)
-    pub fn emergency_return(env: Env, lender: Address, rental_id: u64) {
-        lender.require_auth();
+    /// Return rented tokens
+    pub fn return_tokens(env: Env, borrower: Address, rental_id: u64) -> Result<(), Error> {
+        borrower.require_auth();
+        extend_instance_ttl(&env);
 
-        let mut rental: RentalAgreement = env.storage().persistent()
-            .get(&(RENTAL, rental_id))
-            .unwrap_or_else(|| panic!("Rental not found"));
+        let mut rental = load_rental(&env, rental_id)?;
 
-        if rental.lender != lender {
-            panic!("Unauthorized access");
+        if rental.borrower != borrower {
+            return Err(Error::UnauthorizedReturn);
         }
 
-        if !rental.is_active {
-            panic!("Rental expired");
+        let quote = return_quote(&env, &rental, env.ledger().timestamp())?;
+
+        let token_client = token::Client::new(&env, &rental.token_address);
+        let collateral_client = token::Client::new(&env, &rental.collateral_token);
+
+        repay_principal(&env, &rental, rental.amount);
+
+        if quote.collateral_refund > 0 {
+            collateral_client.transfer(&env.current_contract_address(), &borrower, &quote.collateral_refund);
         }
 
-        let current_time = env.ledger().timestamp();
-        if current_time <= rental.end_time {
-            panic!("Rental still active");
+        // Send penalty to lender as compensation
+        if quote.penalty > 0 {
+            collateral_client.transfer(&env.current_contract_address(), &rental.lender, &quote.penalty);
         }
 
-        let token_client = token::Client::new(&env, &rental.token_address);
+        let earned = rental.rental_fee - quote.fee_refund;
+        release_fees(&env, &mut rental, earned);
+        rental.fee_refunded = quote.fee_refund;
+        if rental.fee_refunded > 0 {
+            token_client.transfer(&env.current_contract_address(), &borrower, &rental.fee_refunded);
+        }
+
+        settle_rental(&env, &mut rental, quote.status, quote.penalty, quote.collateral_refund)?;
+
+        env.events().publish(
+            (symbol_short!("ret_tok"), rental_id),
+            (borrower, rental.lender, quote.status == RentalStatus::Returned)
+        );
+
+        Ok(())
+    }
+
+    /// Return part of the principal before the rental expires, releasing a
+    /// proportional share of the collateral
+    pub fn return_partial(
+        env: Env,
+        borrower: Address,
+        rental_id: u64,
+        amount: i128,
+    ) -> Result<(), Error> {
+        borrower.require_auth();
+        extend_instance_ttl(&env);
+
+        let mut rental = load_rental(&env, rental_id)?;
+
+        if rental.borrower != borrower {
+            return Err(Error::UnauthorizedReturn);
+        }
+
+        require_active(&rental)?;
+
+        // Late rentals settle in full through return_tokens
+        if env.ledger().timestamp() > rental.end_time {
+            return Err(Error::RentalExpired);
+        }
+
+        // Returning everything goes through return_tokens
+        if amount <= 0 || amount >= rental.amount {
+            return Err(Error::InvalidAmount);
+        }
+
+        repay_principal(&env, &rental, amount);
+
+        // Rounds down in the lender's favour
+        let released = rental.collateral_amount * amount / rental.amount;
+        if released > 0 {
+            let collateral_client = token::Client::new(&env, &rental.collateral_token);
+            collateral_client.transfer(&env.current_contract_address(), &borrower, &released);
+        }
+
+        rental.amount -= amount;
+        rental.amount_returned += amount;
+        rental.collateral_amount -= released;
+        rental.collateral_refunded += released;
+        save_rental(&env, &rental);
+
+        env.events().publish(
+            (symbol_short!("ret_part"), rental_id),
+            (borrower, amount, released)
+        );
+
+        Ok(())
+    }
+
+    /// Settle a rental the borrower never returned. Once `end_time` plus the
+    /// platform grace period, or the rental's own late grace period if longer,
+    /// has passed, anyone can mark it defaulted, which forfeits the escrowed
+    /// collateral to the lender.
+    pub fn settle_default(env: Env, rental_id: u64) -> Result<(), Error> {
+        let config = load_config(&env)?;
+        let mut rental = load_rental(&env, rental_id)?;
 
-        // Force return tokens from borrower to lender
-        token_client.transfer(&rental.borrower, &rental.lender, &rental.amount);
+        require_active(&rental)?;
+
+        let grace_period = config.default_grace_period.max(rental.late_policy.grace_period);
+        let current_time = env.ledger().timestamp();
+        if current_time <= rental.end_time.saturating_add(grace_period) {
+            return Err(Error::RentalActive);
+        }
 
         // Forfeit entire collateral to lender
-        token_client.transfer(&env.current_contract_address(), &rental.lender, &rental.collateral_amount);
+        let collateral_client = token::Client::new(&env, &rental.collateral_token);
+        collateral_client.transfer(&env.current_contract_address(), &rental.lender, &rental.collateral_amount);
+
+        // The lender keeps the whole rental fee
+        let rental_fee = rental.rental_fee;
+        release_fees(&env, &mut rental, rental_fee);
+        write_off_principal(&env, &rental);
+
+        let collateral_amount = rental.collateral_amount;
+        settle_rental(&env, &mut rental, RentalStatus::Defaulted, collateral_amount, 0)?;
+
+        env.events().publish(
+            (symbol_short!("default"), rental_id),
+            (rental.lender, rental.borrower, rental.collateral_amount)
+        );
+
+        Ok(())
+    }
+
+    /// Seize the collateral of an under-collateralized rental. Callable by
+    /// anyone; the caller earns the token's liquidation bonus and the rest of
+    /// the collateral goes to the lender.
+    pub fn liquidate(env: Env, liquidator: Address, rental_id: u64) -> Result<(), Error> {
+        liquidator.require_auth();
+        extend_instance_ttl(&env);
+
+        let mut rental = load_rental(&env, rental_id)?;
+
+        require_active(&rental)?;
+
+        let token_config = load_token_config(&env, &rental.token_address)?;
+        let health_factor = oracle::health_factor(&env, &rental)?;
+        if health_factor >= token_config.maintenance_collateral_rate {
+            return Err(Error::RentalHealthy);
+        }
+
+        let bonus = (rental.collateral_amount * token_config.liquidation_bonus_rate) / 10000;
+        let seized = rental.collateral_amount - bonus;
+
+        let collateral_client = token::Client::new(&env, &rental.collateral_token);
+        if bonus > 0 {
+            collateral_client.transfer(&env.current_contract_address(), &liquidator, &bonus);
+        }
+        if seized > 0 {
+            collateral_client.transfer(&env.current_contract_address(), &rental.lender, &seized);
+        }
+
+        // The lender keeps the whole rental fee
+        let rental_fee = rental.rental_fee;
+        release_fees(&env, &mut rental, rental_fee);
+        write_off_principal(&env, &rental);
+
+        let collateral_amount = rental.collateral_amount;
+        settle_rental(&env, &mut rental, RentalStatus::Liquidated, collateral_amount, 0)?;
 
-        // Mark rental as completed
-        rental.is_active = false;
-        rental.is_completed = true;
-        env.storage().persistent().set(&(RENTAL, rental_id), &rental);
+        env.events().publish(
+            (symbol_short!("liquidate"), rental_id),
+            (liquidator, rental.lender, health_factor, seized, bonus)
+        );
+
+        Ok(())
+    }
+
+    /// Pay the lender the escrowed fee earned so far on an active rental.
+    /// Callable by anyone, since funds only ever go to the lender.
+    pub fn claim_earned_fees(env: Env, rental_id: u64) -> Result<i128, Error> {
+        extend_instance_ttl(&env);
+
+        let mut rental = load_rental(&env, rental_id)?;
+
+        require_active(&rental)?;
+
+        let earned = earned_fee(&rental, env.ledger().timestamp());
+        let claimed = release_fees(&env, &mut rental, earned);
+        save_rental(&env, &rental);
 
         env.events().publish(
-            (symbol_short!("emg_ret"), rental_id),
-            (lender, rental.borrower)
+            (symbol_short!("claim_fee"), rental_id),
+            (rental.lender, claimed)
         );
+
+        Ok(claimed)
+    }
+
+    /// Get the collateral value of an active rental as a share of the
+    /// borrowed value (basis points)
+    pub fn get_health_factor(env: Env, rental_id: u64) -> Result<i128, Error> {
+        let rental = load_rental(&env, rental_id)?;
+        oracle::health_factor(&env, &rental)
     }
 
     /// Get rental agreement details
@@ -348,23 +1394,146 @@ impl XRentContract {
         env.storage().persistent().get(&(RENTAL, rental_id))
     }
 
+    /// Quote renting `amount` from a listing for `duration` seconds with the
+    /// given collateral. Fails with the error `rent_tokens` would return.
+    pub fn quote_rental(
+        env: Env,
+        listing_id: u64,
+        amount: i128,
+        duration: u64,
+        collateral_token: Address,
+    ) -> Result<RentalQuote, Error> {
+        let listing = load_listing(&env, listing_id)?;
+        rental_quote(&env, &listing, amount, duration, &collateral_token)
+    }
+
+    /// Quote the settlement of returning a rental in full at `at_timestamp`
+    pub fn quote_return(env: Env, rental_id: u64, at_timestamp: u64) -> Result<ReturnQuote, Error> {
+        let rental = load_rental(&env, rental_id)?;
+        return_quote(&env, &rental, at_timestamp)
+    }
+
     /// Get token listing details
     pub fn get_listing(env: Env, listing_id: u64) -> Option<TokenListing> {
         env.storage().persistent().get(&(LISTING, listing_id))
     }
 
-    /// Get user's rental history
-    pub fn get_user_rentals(env: Env, user: Address) -> Vec<u64> {
-        env.storage().persistent()
-            .get(&(USER_RENTALS, &user))
-            .unwrap_or(Vec::new(&env))
+    /// Browse open listings in the order they opened, starting after listing
+    /// `cursor` (0 to start from the beginning). A page may hold fewer than
+    /// `limit` listings while `next_cursor` is non-zero when filtering skips
+    /// many entries.
+    pub fn list_available(
+        env: Env,
+        cursor: u64,
+        limit: u32,
+        filter: ListingFilter,
+    ) -> Result<ListingPage, Error> {
+        listing_page(&env, &IndexKey::OpenListings, cursor, limit, &filter)
+    }
+
+    /// Open listings of a lender in the order they opened, starting after
+    /// listing `cursor` (0 to start from the beginning)
+    pub fn get_open_listings(
+        env: Env,
+        lender: Address,
+        cursor: u64,
+        limit: u32,
+    ) -> Result<ListingPage, Error> {
+        let index = IndexKey::LenderListings(lender);
+        listing_page(&env, &index, cursor, limit, &ListingFilter::default())
+    }
+
+    /// Active rentals of a lender in id order, starting after rental `cursor`
+    /// (0 to start from the beginning)
+    pub fn get_lender_rentals(
+        env: Env,
+        lender: Address,
+        cursor: u64,
+        limit: u32,
+    ) -> Result<RentalPage, Error> {
+        let index = IndexKey::LenderRentals(lender);
+        rental_page(&env, &index, cursor, limit, u64::MAX)
+    }
+
+    /// Active rentals that ended before `now`, earliest expiry day first,
+    /// starting after rental `cursor` (0 to start from the beginning)
+    pub fn get_overdue_rentals(
+        env: Env,
+        now: u64,
+        cursor: u64,
+        limit: u32,
+    ) -> Result<RentalPage, Error> {
+        rental_page(&env, &IndexKey::ActiveRentals, cursor, limit, now)
+    }
+
+    /// Active rentals of a borrower in id order, starting after rental
+    /// `cursor` (0 to start from the beginning)
+    pub fn get_active_rentals(
+        env: Env,
+        borrower: Address,
+        cursor: u64,
+        limit: u32,
+    ) -> Result<RentalPage, Error> {
+        let index = IndexKey::BorrowerRentals(borrower);
+        rental_page(&env, &index, cursor, limit, u64::MAX)
+    }
+
+    /// Page `page` of a user's rental history, oldest first. Pages hold up to
+    /// 50 rental ids. Old pages that were archived can't be read until they are
+    /// restored; pages past the end come back empty.
+    pub fn get_user_rentals(env: Env, user: Address, page: u32) -> Vec<u64> {
+        history::page(&env, &HistoryKey::Rentals(user), page)
+    }
+
+    /// Number of rentals a user has taken out
+    pub fn get_user_rental_count(env: Env, user: Address) -> u32 {
+        history::len(&env, &HistoryKey::Rentals(user))
     }
 
-    /// Get user's listings
-    pub fn get_user_listings(env: Env, user: Address) -> Vec<u64> {
-        env.storage().persistent()
-            .get(&(USER_LISTINGS, &user))
-            .unwrap_or(Vec::new(&env))
+    /// Page `page` of a user's listings, oldest first. Pages hold up to 50
+    /// listing ids. Old pages that were archived can't be read until they are
+    /// restored; pages past the end come back empty.
+    pub fn get_user_listings(env: Env, user: Address, page: u32) -> Vec<u64> {
+        history::page(&env, &HistoryKey::Listings(user), page)
+    }
+
+    /// Number of listings a user has created
+    pub fn get_user_listing_count(env: Env, user: Address) -> u32 {
+        history::len(&env, &HistoryKey::Listings(user))
+    }
+
+    /// Extend the storage lifetime of a rental and its borrower's history.
+    /// Callable by anyone; active rentals are kept until past `end_time`.
+    pub fn bump_rental(env: Env, rental_id: u64) -> Result<(), Error> {
+        extend_instance_ttl(&env);
+
+        let rental = load_rental(&env, rental_id)?;
+        let ttl = rental_ttl(&env, &rental);
+
+        extend_record_ttl(&env, &(RENTAL, rental_id), ttl);
+        history::bump(&env, &HistoryKey::Rentals(rental.borrower.clone()), ttl);
+        index::bump(&env, &IndexKey::LenderRentals(rental.lender.clone()), rental_id, ttl);
+        index::bump(&env, &IndexKey::BorrowerRentals(rental.borrower.clone()), rental_id, ttl);
+        index::bump(&env, &IndexKey::ActiveRentals, rental_id, ttl);
+
+        Ok(())
+    }
+
+    /// Extend the storage lifetime of a listing and its lender's listings.
+    /// Callable by anyone.
+    pub fn bump_listing(env: Env, listing_id: u64) -> Result<(), Error> {
+        extend_instance_ttl(&env);
+
+        let listing = load_listing(&env, listing_id)?;
+        let ttl = escrow_ttl(&env, listing.available_amount);
+
+        extend_record_ttl(&env, &(LISTING, listing_id), ttl);
+        history::bump(&env, &HistoryKey::Listings(listing.lender.clone()), RECORD_BUMP_AMOUNT);
+        index::bump(&env, &IndexKey::OpenListings, listing_id, ttl);
+        let lender_index = IndexKey::LenderListings(listing.lender.clone());
+        index::bump(&env, &lender_index, listing_id, ttl);
+
+        Ok(())
     }
 
     /// Get platform configuration
@@ -372,35 +1541,546 @@ impl XRentContract {
         env.storage().instance().get(&CONFIG)
     }
 
-    /// Cancel a listing (only by lender)
-    pub fn cancel_listing(env: Env, lender: Address, listing_id: u64) {
+    /// Update the platform fee rate (only by admin)
+    pub fn set_platform_fee_rate(env: Env, platform_fee_rate: i128) -> Result<(), Error> {
+        let mut config = load_config(&env)?;
+        config.admin.require_auth();
+
+        let old = config.platform_fee_rate;
+        config.platform_fee_rate = platform_fee_rate;
+        save_config(&env, &config)?;
+
+        env.events().publish(
+            (symbol_short!("cfg_upd"), symbol_short!("fee_rate")),
+            (old, platform_fee_rate)
+        );
+
+        Ok(())
+    }
+
+    /// Update the minimum collateral rate for new listings (only by admin)
+    pub fn set_min_collateral_rate(env: Env, min_collateral_rate: i128) -> Result<(), Error> {
+        let mut config = load_config(&env)?;
+        config.admin.require_auth();
+
+        let old = config.min_collateral_rate;
+        config.min_collateral_rate = min_collateral_rate;
+        save_config(&env, &config)?;
+
+        env.events().publish(
+            (symbol_short!("cfg_upd"), symbol_short!("min_coll")),
+            (old, min_collateral_rate)
+        );
+
+        Ok(())
+    }
+
+    /// Update the maximum rental duration in seconds (only by admin)
+    pub fn set_max_rental_duration(env: Env, max_rental_duration: u64) -> Result<(), Error> {
+        let mut config = load_config(&env)?;
+        config.admin.require_auth();
+
+        let old = config.max_rental_duration;
+        config.max_rental_duration = max_rental_duration;
+        save_config(&env, &config)?;
+
+        env.events().publish(
+            (symbol_short!("cfg_upd"), symbol_short!("max_dur")),
+            (old, max_rental_duration)
+        );
+
+        Ok(())
+    }
+
+    /// Set the upper bounds for the late policies lenders may choose. Existing
+    /// listings keep their policy.
+    pub fn set_max_late_policy(env: Env, max_late_policy: LatePolicy) -> Result<(), Error> {
+        let mut config = load_config(&env)?;
+        config.admin.require_auth();
+
+        let old = config.max_late_policy.clone();
+        config.max_late_policy = max_late_policy.clone();
+        save_config(&env, &config)?;
+
+        env.events().publish(
+            (symbol_short!("cfg_upd"), symbol_short!("late")),
+            (old, max_late_policy)
+        );
+
+        Ok(())
+    }
+
+    /// Update the grace period after `end_time` before a rental can be
+    /// defaulted, in seconds (only by admin)
+    pub fn set_default_grace_period(env: Env, default_grace_period: u64) -> Result<(), Error> {
+        let mut config = load_config(&env)?;
+        config.admin.require_auth();
+
+        let old = config.default_grace_period;
+        config.default_grace_period = default_grace_period;
+        save_config(&env, &config)?;
+
+        env.events().publish(
+            (symbol_short!("cfg_upd"), symbol_short!("grace")),
+            (old, default_grace_period)
+        );
+
+        Ok(())
+    }
+
+    /// Propose a new admin; takes effect once accepted by the new admin
+    pub fn propose_admin(env: Env, new_admin: Address) -> Result<(), Error> {
+        let config = load_config(&env)?;
+        config.admin.require_auth();
+
+        env.storage().instance().set(&PENDING_ADMIN, &new_admin);
+
+        env.events().publish(
+            (symbol_short!("adm_prop"),),
+            (config.admin, new_admin)
+        );
+
+        Ok(())
+    }
+
+    /// Accept a pending admin proposal (only by the proposed admin)
+    pub fn accept_admin(env: Env) -> Result<(), Error> {
+        let mut config = load_config(&env)?;
+
+        let new_admin: Address = env.storage().instance()
+            .get(&PENDING_ADMIN)
+            .ok_or(Error::NoPendingAdmin)?;
+        new_admin.require_auth();
+
+        let old_admin = config.admin;
+        config.admin = new_admin.clone();
+        env.storage().instance().set(&CONFIG, &config);
+        env.storage().instance().remove(&PENDING_ADMIN);
+
+        env.events().publish(
+            (symbol_short!("adm_acc"),),
+            (old_admin, new_admin)
+        );
+
+        Ok(())
+    }
+
+    /// Get the admin proposed by `propose_admin`, if any
+    pub fn get_pending_admin(env: Env) -> Option<Address> {
+        env.storage().instance().get(&PENDING_ADMIN)
+    }
+
+    /// Set or clear the pauser, who may restrict but not resume the contract (only by admin)
+    pub fn set_pauser(env: Env, pauser: Option<Address>) -> Result<(), Error> {
+        let config = load_config(&env)?;
+        config.admin.require_auth();
+
+        match &pauser {
+            Some(pauser) => env.storage().instance().set(&PAUSER, pauser),
+            None => env.storage().instance().remove(&PAUSER),
+        }
+
+        env.events().publish(
+            (symbol_short!("pauser"),),
+            (pauser,)
+        );
+
+        Ok(())
+    }
+
+    /// Get the current pauser, if any
+    pub fn get_pauser(env: Env) -> Option<Address> {
+        env.storage().instance().get(&PAUSER)
+    }
+
+    /// Switch the circuit breaker mode. The admin may set any mode; the
+    /// pauser may only move to a more restrictive one.
+    pub fn set_mode(env: Env, caller: Address, mode: ContractMode) -> Result<(), Error> {
+        caller.require_auth();
+
+        let config = load_config(&env)?;
+        let old = current_mode(&env);
+
+        if caller != config.admin {
+            let pauser: Option<Address> = env.storage().instance().get(&PAUSER);
+            if pauser != Some(caller.clone()) || mode <= old {
+                return Err(Error::UnauthorizedAccess);
+            }
+        }
+
+        env.storage().instance().set(&MODE, &mode);
+
+        env.events().publish(
+            (symbol_short!("mode"),),
+            (caller, old, mode)
+        );
+
+        Ok(())
+    }
+
+    /// Get the current circuit breaker mode
+    pub fn get_mode(env: Env) -> ContractMode {
+        current_mode(&env)
+    }
+
+    /// Replace the contract code (only by admin). Storage is kept as is;
+    /// call `migrate` afterwards if the new code bumps the storage version.
+    pub fn upgrade(env: Env, new_wasm_hash: BytesN<32>) -> Result<(), Error> {
+        let config = load_config(&env)?;
+        config.admin.require_auth();
+
+        env.deployer().update_current_contract_wasm(new_wasm_hash.clone());
+
+        env.events().publish(
+            (symbol_short!("upgrade"),),
+            (new_wasm_hash,)
+        );
+
+        Ok(())
+    }
+
+    /// Get the contract code version
+    pub fn version() -> u32 {
+        CONTRACT_VERSION
+    }
+
+    /// Get the storage schema version currently in use
+    pub fn storage_version(env: Env) -> u32 {
+        storage_version(&env)
+    }
+
+    /// Rewrite up to `limit` stored listings and rentals into the current
+    /// layout (only by admin). Call repeatedly until it returns `true`.
+    pub fn migrate(env: Env, limit: u32) -> Result<bool, Error> {
+        let from_version = storage_version(&env);
+        if from_version >= STORAGE_VERSION {
+            return Err(Error::MigrationNotNeeded);
+        }
+
+        // The config is rewritten up front so the admin can be checked
+        let cursor: Option<MigrationCursor> = env.storage().instance().get(&MIGRATION);
+        if cursor.is_none() {
+            migration::migrate_config(&env);
+        }
+
+        let config = load_config(&env)?;
+        config.admin.require_auth();
+
+        let mut cursor = cursor.unwrap_or_else(|| MigrationCursor::start(&env));
+
+        let done = migration::migrate_records(&env, &mut cursor, limit);
+
+        if done {
+            env.storage().instance().remove(&MIGRATION);
+            env.storage().instance().set(&SCHEMA, &STORAGE_VERSION);
+
+            env.events().publish(
+                (symbol_short!("migrated"),),
+                (from_version, STORAGE_VERSION)
+            );
+        } else {
+            env.storage().instance().set(&MIGRATION, &cursor);
+        }
+
+        Ok(done)
+    }
+
+    /// Add a token to the registry or update its risk parameters (only by admin)
+    pub fn set_token_config(env: Env, token_config: TokenConfig) -> Result<(), Error> {
+        let config = load_config(&env)?;
+        config.admin.require_auth();
+
+        validate_token_config(&token_config, &config)?;
+
+        let mut tokens = supported_tokens(&env);
+        tokens.set(token_config.token_address.clone(), token_config.clone());
+        env.storage().instance().set(&TOKENS, &tokens);
+
+        env.events().publish(
+            (symbol_short!("tok_set"), token_config.token_address.clone()),
+            (token_config,)
+        );
+
+        Ok(())
+    }
+
+    /// Remove a token from the registry (only by admin). Existing listings and
+    /// rentals can still be unwound, but no new ones are accepted.
+    pub fn remove_token(env: Env, token_address: Address) -> Result<(), Error> {
+        let config = load_config(&env)?;
+        config.admin.require_auth();
+
+        let mut tokens = supported_tokens(&env);
+        if tokens.remove(token_address.clone()).is_none() {
+            return Err(Error::TokenNotSupported);
+        }
+        env.storage().instance().set(&TOKENS, &tokens);
+
+        env.events().publish(
+            (symbol_short!("tok_rm"), token_address),
+            ()
+        );
+
+        Ok(())
+    }
+
+    /// Get the registry entry for a token
+    pub fn get_token_config(env: Env, token_address: Address) -> Option<TokenConfig> {
+        supported_tokens(&env).get(token_address)
+    }
+
+    /// List all supported tokens with their risk parameters
+    pub fn list_supported_tokens(env: Env) -> Vec<TokenConfig> {
+        supported_tokens(&env).values()
+    }
+
+    /// Set the price feed used to value collateral in a different asset than
+    /// the rented one (only by admin)
+    pub fn set_oracle(env: Env, oracle_config: OracleConfig) -> Result<(), Error> {
+        let config = load_config(&env)?;
+        config.admin.require_auth();
+
+        if oracle_config.max_price_age == 0 {
+            return Err(Error::InvalidConfig);
+        }
+
+        env.storage().instance().set(&ORACLE, &oracle_config);
+
+        env.events().publish(
+            (symbol_short!("oracle"),),
+            (oracle_config.oracle, oracle_config.max_price_age)
+        );
+
+        Ok(())
+    }
+
+    /// Get the price feed configuration, if any
+    pub fn get_oracle(env: Env) -> Option<OracleConfig> {
+        env.storage().instance().get(&ORACLE)
+    }
+
+    /// Get platform fees accrued and not yet withdrawn for a token
+    pub fn get_accrued_fees(env: Env, token_address: Address) -> i128 {
+        accrued_fees(&env, &token_address)
+    }
+
+    /// Withdraw accrued platform fees (only by admin)
+    pub fn withdraw_fees(
+        env: Env,
+        token_address: Address,
+        to: Address,
+        amount: i128,
+    ) -> Result<(), Error> {
+        let config = load_config(&env)?;
+        config.admin.require_auth();
+
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+
+        let accrued = accrued_fees(&env, &token_address);
+        if amount > accrued {
+            return Err(Error::InsufficientBalance);
+        }
+
+        let remaining = accrued - amount;
+        env.storage().persistent().set(&(ACCRUED_FEES, &token_address), &remaining);
+        extend_record_ttl(&env, &(ACCRUED_FEES, &token_address), escrow_ttl(&env, remaining));
+
+        let token_client = token::Client::new(&env, &token_address);
+        token_client.transfer(&env.current_contract_address(), &to, &amount);
+
+        env.events().publish(
+            (symbol_short!("fee_wd"), token_address),
+            (to, amount, remaining)
+        );
+
+        Ok(())
+    }
+
+    /// Change the pricing and duration terms of an open listing (only by
+    /// lender). Terms are frozen while any of the listing is rented out.
+    pub fn update_listing(
+        env: Env,
+        lender: Address,
+        listing_id: u64,
+        rental_rate: i128,
+        min_duration: u64,
+        max_duration: u64,
+        collateral_rate: i128,
+    ) -> Result<(), Error> {
+        lender.require_auth();
+        require_mode(&env, ContractMode::Paused)?;
+
+        let mut listing = load_listing(&env, listing_id)?;
+
+        if listing.lender != lender {
+            return Err(Error::UnauthorizedAccess);
+        }
+
+        if !listing.is_available {
+            return Err(Error::ListingNotAvailable);
+        }
+
+        if listing.rented_amount > 0 {
+            return Err(Error::ListingRented);
+        }
+
+        let config = load_config(&env)?;
+        let token_config = load_token_config(&env, &listing.token_address)?;
+        validate_listing_terms(
+            &config,
+            &token_config,
+            rental_rate,
+            min_duration,
+            max_duration,
+            collateral_rate,
+            &listing.options.fee_model,
+        )?;
+
+        listing.rental_rate = rental_rate;
+        listing.min_duration = min_duration;
+        listing.max_duration = max_duration;
+        listing.collateral_rate = collateral_rate;
+        save_listing(&env, &listing);
+
+        env.events().publish(
+            (symbol_short!("upd_list"), listing_id),
+            (lender, rental_rate, min_duration, max_duration, collateral_rate)
+        );
+
+        Ok(())
+    }
+
+    /// Escrow `amount` more tokens in a listing (only by lender). Reopens a
+    /// fully drawn standing listing.
+    pub fn top_up_listing(env: Env, lender: Address, listing_id: u64, amount: i128) -> Result<(), Error> {
+        lender.require_auth();
+        require_mode(&env, ContractMode::Paused)?;
+
+        let mut listing = load_listing(&env, listing_id)?;
+
+        if listing.lender != lender {
+            return Err(Error::UnauthorizedAccess);
+        }
+
+        let now = env.ledger().timestamp();
+        if !(listing.is_available || listing.options.standing) || listing_expired(&listing, now) {
+            return Err(Error::ListingNotAvailable);
+        }
+
+        let token_config = load_token_config(&env, &listing.token_address)?;
+        if amount <= 0 || listing.available_amount + amount < token_config.min_amount {
+            return Err(Error::InvalidAmount);
+        }
+
+        let token_client = token::Client::new(&env, &listing.token_address);
+        token_client.transfer(&lender, &env.current_contract_address(), &amount);
+
+        listing.amount += amount;
+        listing.available_amount += amount;
+        listing.is_available = true;
+        save_listing(&env, &listing);
+
+        env.events().publish(
+            (symbol_short!("top_up"), listing_id),
+            (lender, amount, listing.available_amount)
+        );
+
+        Ok(())
+    }
+
+    /// Take `amount` undrawn tokens back out of a listing (only by lender).
+    /// Whatever stays listed must still meet the token's minimum amount.
+    /// Allowed in every mode, as it only unwinds a position.
+    pub fn withdraw_from_listing(
+        env: Env,
+        lender: Address,
+        listing_id: u64,
+        amount: i128,
+    ) -> Result<(), Error> {
         lender.require_auth();
+        extend_instance_ttl(&env);
 
-        let mut listing: TokenListing = env.storage().persistent()
-            .get(&(LISTING, listing_id))
-            .unwrap_or_else(|| panic!("Rental not found"));
+        let mut listing = load_listing(&env, listing_id)?;
 
         if listing.lender != lender {
-            panic!("Unauthorized access");
+            return Err(Error::UnauthorizedAccess);
         }
 
         if !listing.is_available {
-            panic!("Listing not available");
+            return Err(Error::ListingNotAvailable);
+        }
+
+        if amount <= 0 || amount > listing.available_amount {
+            return Err(Error::InvalidAmount);
+        }
+
+        // Tokens that have since been delisted can still be withdrawn in full
+        let remaining = listing.available_amount - amount;
+        let min_amount = supported_tokens(&env)
+            .get(listing.token_address.clone())
+            .map(|token_config| token_config.min_amount)
+            .unwrap_or(0);
+        if remaining > 0 && remaining < min_amount {
+            return Err(Error::InvalidAmount);
         }
 
-        // Return tokens to lender
         let token_client = token::Client::new(&env, &listing.token_address);
-        token_client.transfer(&env.current_contract_address(), &lender, &listing.amount);
+        token_client.transfer(&env.current_contract_address(), &lender, &amount);
 
-        // Mark listing as unavailable
+        listing.amount -= amount;
+        listing.available_amount = remaining;
+        listing.is_available = remaining > 0;
+        save_listing(&env, &listing);
+
+        env.events().publish(
+            (symbol_short!("wd_list"), listing_id),
+            (lender, amount, remaining)
+        );
+
+        Ok(())
+    }
+
+    /// Cancel a listing (only by lender)
+    pub fn cancel_listing(env: Env, lender: Address, listing_id: u64) -> Result<(), Error> {
+        lender.require_auth();
+        extend_instance_ttl(&env);
+
+        let mut listing = load_listing(&env, listing_id)?;
+
+        if listing.lender != lender {
+            return Err(Error::UnauthorizedAccess);
+        }
+
+        // A fully drawn standing listing can still be cancelled to stop it reopening
+        if !listing.is_available && !listing.options.standing {
+            return Err(Error::ListingNotAvailable);
+        }
+
+        // Return undrawn tokens to lender; rented portions are repaid by borrowers
+        let refund = listing.available_amount;
+        let token_client = token::Client::new(&env, &listing.token_address);
+        token_client.transfer(&env.current_contract_address(), &lender, &refund);
+
+        // Mark listing as unavailable for good
+        listing.available_amount = 0;
+        listing.options.standing = false;
         listing.is_available = false;
-        env.storage().persistent().set(&(LISTING, listing_id), &listing);
+        save_listing(&env, &listing);
 
         env.events().publish(
             (symbol_short!("can_list"), listing_id),
-            (lender,)
+            (lender, refund)
         );
+
+        Ok(())
     }
 }
 
+mod history;
+mod index;
+mod migration;
+mod oracle;
+mod pricing;
 mod test;
@@ -0,0 +1,217 @@
+//! Storage layout migration for contracts upgraded in place.
+//!
+//! Schema version 1 is the layout written by the original deployment, which
+//! predates the schema version key. Each `*V1` type mirrors a record exactly as
+//! it was stored back then and knows how to convert itself into the current
+//! layout.
+
+use soroban_sdk::{contracttype, symbol_short, Address, Env, Symbol, Vec};
+
+use crate::history::{self, HistoryKey};
+use crate::{
+    rental_ttl, save_listing, save_rental, FeeModel, LatePolicy, ListingOptions, PlatformConfig,
+    RentalAgreement, RentalStatus, TokenListing, CONFIG, DAY_IN_SECONDS, LISTING, NEXT_LISTING_ID,
+    NEXT_RENTAL_ID, RECORD_BUMP_AMOUNT, RENTAL,
+};
+
+/// Unbounded per-user id vectors, since replaced by paged histories
+const LEGACY_USER_RENTALS: Symbol = symbol_short!("u_rents");
+const LEGACY_USER_LISTINGS: Symbol = symbol_short!("u_lists");
+
+/// Grace period given to deployments that predate configurable defaults
+const LEGACY_DEFAULT_GRACE_PERIOD: u64 = 3 * 24 * 60 * 60;
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct RentalAgreementV1 {
+    pub rental_id: u64,
+    pub lender: Address,
+    pub borrower: Address,
+    pub token_address: Address,
+    pub amount: i128,
+    pub collateral_amount: i128,
+    pub rental_fee: i128,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub is_active: bool,
+    pub is_completed: bool,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct TokenListingV1 {
+    pub listing_id: u64,
+    pub lender: Address,
+    pub token_address: Address,
+    pub amount: i128,
+    pub rental_rate: i128,
+    pub min_duration: u64,
+    pub max_duration: u64,
+    pub collateral_rate: i128,
+    pub is_available: bool,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct PlatformConfigV1 {
+    pub admin: Address,
+    pub platform_fee_rate: i128,
+    pub min_collateral_rate: i128,
+    pub max_rental_duration: u64,
+}
+
+/// Progress of an in-flight migration: the next record ids to rewrite, and
+/// the ids the legacy records stop at
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct MigrationCursor {
+    pub next_listing_id: u64,
+    pub next_rental_id: u64,
+    pub end_listing_id: u64,
+    pub end_rental_id: u64,
+}
+
+impl MigrationCursor {
+    /// Start a migration over every record written before it began
+    pub(crate) fn start(env: &Env) -> Self {
+        MigrationCursor {
+            next_listing_id: 1,
+            next_rental_id: 1,
+            end_listing_id: env.storage().instance().get(&NEXT_LISTING_ID).unwrap_or(1),
+            end_rental_id: env.storage().instance().get(&NEXT_RENTAL_ID).unwrap_or(1),
+        }
+    }
+}
+
+impl PlatformConfigV1 {
+    fn into_current(self) -> PlatformConfig {
+        PlatformConfig {
+            admin: self.admin,
+            platform_fee_rate: self.platform_fee_rate,
+            min_collateral_rate: self.min_collateral_rate,
+            // Durations used to be whole days
+            max_rental_duration: self.max_rental_duration * DAY_IN_SECONDS,
+            default_grace_period: LEGACY_DEFAULT_GRACE_PERIOD,
+            // Lenders start out limited to the old flat penalty
+            max_late_policy: LatePolicy::default(),
+        }
+    }
+}
+
+impl RentalAgreementV1 {
+    fn into_current(self, platform_fee_rate: i128) -> RentalAgreement {
+        // Rates weren't recorded; recover the daily rate from the fee paid
+        let days = (self.end_time.saturating_sub(self.start_time) / DAY_IN_SECONDS) as i128;
+        let rental_rate = if self.amount > 0 && days > 0 {
+            self.rental_fee * 10000 / (self.amount * days)
+        } else {
+            0
+        };
+
+        RentalAgreement {
+            rental_id: self.rental_id,
+            listing_id: 0, // Not recorded by the original layout
+            lender: self.lender,
+            borrower: self.borrower,
+            token_address: self.token_address.clone(),
+            amount: self.amount,
+            amount_returned: 0,
+            // Collateral used to be taken in the rented token
+            collateral_token: self.token_address,
+            collateral_amount: self.collateral_amount,
+            rental_fee: self.rental_fee,
+            fee_model: FeeModel::Daily,
+            rental_rate,
+            platform_fee_rate,
+            // Fees used to be paid to the lender up front
+            fee_escrowed: false,
+            fees_claimed: self.rental_fee,
+            fee_refunded: 0,
+            start_time: self.start_time,
+            end_time: self.end_time,
+            // Settled rentals didn't record their outcome or settlement time
+            status: if self.is_active { RentalStatus::Active } else { RentalStatus::Returned },
+            settled_at: 0,
+            penalty_paid: 0,
+            collateral_refunded: 0,
+            late_policy: LatePolicy::default(),
+        }
+    }
+}
+
+impl TokenListingV1 {
+    fn into_current(self, env: &Env) -> TokenListing {
+        TokenListing {
+            listing_id: self.listing_id,
+            lender: self.lender,
+            token_address: self.token_address.clone(),
+            amount: self.amount,
+            // Listings used to be rented out whole
+            available_amount: if self.is_available { self.amount } else { 0 },
+            // Legacy rentals aren't linked to their listing
+            rented_amount: 0,
+            rental_rate: self.rental_rate,
+            // Durations used to be whole days
+            min_duration: self.min_duration * DAY_IN_SECONDS,
+            max_duration: self.max_duration * DAY_IN_SECONDS,
+            collateral_rate: self.collateral_rate,
+            collateral_tokens: Vec::from_array(env, [self.token_address]),
+            options: ListingOptions::default(),
+            is_available: self.is_available,
+        }
+    }
+}
+
+/// Rewrite the platform config into the current layout
+pub(crate) fn migrate_config(env: &Env) {
+    let old: Option<PlatformConfigV1> = env.storage().instance().get(&CONFIG);
+    if let Some(old) = old {
+        env.storage().instance().set(&CONFIG, &old.into_current());
+    }
+}
+
+/// Rewrite up to `limit` records starting at `cursor`. Listings are migrated
+/// before rentals, and after the config, whose fee rate legacy rentals adopt.
+/// Each record is added to its owner's paged history in id order, replacing
+/// the legacy per-user vectors. Returns `true` once every record has been
+/// rewritten.
+pub(crate) fn migrate_records(env: &Env, cursor: &mut MigrationCursor, limit: u32) -> bool {
+    let platform_fee_rate = env.storage().instance()
+        .get::<_, PlatformConfig>(&CONFIG)
+        .map(|config| config.platform_fee_rate)
+        .unwrap_or(0);
+
+    let mut budget = limit;
+
+    while budget > 0 && cursor.next_listing_id < cursor.end_listing_id {
+        let old: Option<TokenListingV1> = env.storage().persistent()
+            .get(&(LISTING, cursor.next_listing_id));
+        if let Some(old) = old {
+            let listing = old.into_current(env);
+            save_listing(env, &listing);
+
+            let history = HistoryKey::Listings(listing.lender.clone());
+            history::push(env, &history, listing.listing_id, RECORD_BUMP_AMOUNT);
+            env.storage().persistent().remove(&(LEGACY_USER_LISTINGS, &listing.lender));
+        }
+        cursor.next_listing_id += 1;
+        budget -= 1;
+    }
+
+    while budget > 0 && cursor.next_rental_id < cursor.end_rental_id {
+        let old: Option<RentalAgreementV1> = env.storage().persistent()
+            .get(&(RENTAL, cursor.next_rental_id));
+        if let Some(old) = old {
+            let rental = old.into_current(platform_fee_rate);
+            save_rental(env, &rental);
+
+            let history = HistoryKey::Rentals(rental.borrower.clone());
+            history::push(env, &history, rental.rental_id, rental_ttl(env, &rental));
+            env.storage().persistent().remove(&(LEGACY_USER_RENTALS, &rental.borrower));
+        }
+        cursor.next_rental_id += 1;
+        budget -= 1;
+    }
+
+    cursor.next_listing_id >= cursor.end_listing_id && cursor.next_rental_id >= cursor.end_rental_id
+}
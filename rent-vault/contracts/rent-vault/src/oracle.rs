@@ -0,0 +1,154 @@
+//! Collateral valuation through a SEP-40 price feed.
+//!
+//! Each token is priced by the feed and staleness bound named in its registry
+//! entry, falling back to the platform oracle for either. Prices are
+//! normalized to `PRICE_DECIMALS` so quotes from feeds with different precision
+//! can be compared.
+
+use soroban_sdk::{contractclient, contracttype, token, Address, Env, Symbol};
+
+use crate::{load_token_config, Error, RentalAgreement, ORACLE};
+
+/// Precision prices are normalized to, matching common SEP-40 feeds
+const PRICE_DECIMALS: u32 = 14;
+
+/// Asset identifier as defined by SEP-40
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Asset {
+    Stellar(Address),
+    Other(Symbol),
+}
+
+/// Price record as defined by SEP-40
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PriceData {
+    pub price: i128,
+    pub timestamp: u64,
+}
+
+/// Price feed used to value collateral against the rented asset
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OracleConfig {
+    pub oracle: Address,
+    pub max_price_age: u64, // Prices older than this many seconds are rejected
+}
+
+/// The subset of the SEP-40 interface the vault relies on. Only the generated
+/// client is used.
+#[allow(dead_code)]
+#[contractclient(name = "PriceOracleClient")]
+pub trait PriceOracle {
+    fn lastprice(env: Env, asset: Asset) -> Option<PriceData>;
+    fn decimals(env: Env) -> u32;
+}
+
+pub(crate) fn load_oracle_config(env: &Env) -> Result<OracleConfig, Error> {
+    env.storage().instance()
+        .get(&ORACLE)
+        .ok_or(Error::OracleNotConfigured)
+}
+
+/// Feed and staleness bound for a token. The platform oracle is only needed
+/// for whatever the token's registry entry doesn't set itself.
+fn price_source(env: &Env, token_address: &Address) -> Result<OracleConfig, Error> {
+    let (feed, max_price_age) = load_token_config(env, token_address)
+        .map(|token_config| (token_config.price_feed, token_config.max_price_age))
+        .unwrap_or((None, None));
+
+    match (feed, max_price_age) {
+        (Some(oracle), Some(max_price_age)) => Ok(OracleConfig { oracle, max_price_age }),
+        (feed, max_price_age) => {
+            let platform = load_oracle_config(env)?;
+            Ok(OracleConfig {
+                oracle: feed.unwrap_or(platform.oracle),
+                max_price_age: max_price_age.unwrap_or(platform.max_price_age),
+            })
+        }
+    }
+}
+
+/// Latest price of a token in `PRICE_DECIMALS`, read from the token's own feed
+/// if the registry names one and from the platform feed otherwise. Missing and
+/// stale quotes are rejected.
+pub(crate) fn fetch_price(env: &Env, token_address: &Address) -> Result<i128, Error> {
+    let oracle_config = price_source(env, token_address)?;
+
+    let client = PriceOracleClient::new(env, &oracle_config.oracle);
+    let data = client
+        .lastprice(&Asset::Stellar(token_address.clone()))
+        .ok_or(Error::PriceUnavailable)?;
+
+    if data.price <= 0 {
+        return Err(Error::PriceUnavailable);
+    }
+
+    if data.timestamp.saturating_add(oracle_config.max_price_age) < env.ledger().timestamp() {
+        return Err(Error::StalePrice);
+    }
+
+    // Feeds may quote with different precision
+    let decimals = client.decimals();
+    let price = if decimals <= PRICE_DECIMALS {
+        data.price * 10i128.pow(PRICE_DECIMALS - decimals)
+    } else {
+        data.price / 10i128.pow(decimals - PRICE_DECIMALS)
+    };
+
+    if price <= 0 {
+        return Err(Error::PriceUnavailable);
+    }
+
+    Ok(price)
+}
+
+/// Value of `amount` units of a token, in `PRICE_DECIMALS`
+fn token_value(env: &Env, token_address: &Address, amount: i128) -> Result<i128, Error> {
+    let price = fetch_price(env, token_address)?;
+    let decimals = token::Client::new(env, token_address).decimals();
+    Ok(amount * price / 10i128.pow(decimals))
+}
+
+/// Collateral, in units of `collateral_token`, worth `collateral_rate` basis
+/// points of `amount` units of `token_address`. Rounds up in the lender's favour.
+pub(crate) fn required_collateral(
+    env: &Env,
+    token_address: &Address,
+    amount: i128,
+    collateral_token: &Address,
+    collateral_rate: i128,
+) -> Result<i128, Error> {
+    if collateral_token == token_address {
+        return Ok((amount * collateral_rate) / 10000);
+    }
+
+    let required_value = token_value(env, token_address, amount)? * collateral_rate / 10000;
+
+    let collateral_price = fetch_price(env, collateral_token)?;
+    let collateral_decimals = token::Client::new(env, collateral_token).decimals();
+
+    let numerator = required_value * 10i128.pow(collateral_decimals);
+    Ok((numerator + collateral_price - 1) / collateral_price)
+}
+
+/// Current collateral value as a share of the outstanding principal's value,
+/// in basis points
+pub(crate) fn health_factor(env: &Env, rental: &RentalAgreement) -> Result<i128, Error> {
+    if rental.amount <= 0 {
+        return Ok(i128::MAX);
+    }
+
+    if rental.collateral_token == rental.token_address {
+        return Ok(rental.collateral_amount * 10000 / rental.amount);
+    }
+
+    let value = token_value(env, &rental.token_address, rental.amount)?;
+    if value <= 0 {
+        return Ok(i128::MAX);
+    }
+
+    let collateral_value = token_value(env, &rental.collateral_token, rental.collateral_amount)?;
+    Ok(collateral_value * 10000 / value)
+}
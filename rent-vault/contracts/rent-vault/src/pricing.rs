@@ -0,0 +1,136 @@
+//! Rental fee models.
+//!
+//! Every fee the contract charges, whether for a new rental, an extension or a
+//! quote, is computed here so the numbers always agree. Rates are in basis
+//! points of the rented principal and durations in seconds.
+
+use soroban_sdk::{contracttype, Vec};
+
+use crate::{Error, DAY_IN_SECONDS};
+
+/// Most rate tiers a listing may define
+const MAX_RATE_TIERS: u32 = 10;
+
+/// How a listing prices its rentals from its `rental_rate`
+#[contracttype]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum FeeModel {
+    #[default]
+    Daily, // rental_rate per started day
+    PerSecond, // rental_rate per day, accrued by the second
+    Flat, // rental_rate once, whatever the duration
+    Tiered(Vec<RateTier>), // Daily, at the rate of the longest tier reached
+}
+
+/// Daily rate applied to rentals of at least `min_duration` seconds
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RateTier {
+    pub min_duration: u64,
+    pub rental_rate: i128, // Fee percentage per day (basis points)
+}
+
+/// Breakdown of the fees for renting `amount` for a given duration
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct FeeQuote {
+    pub fee_model: FeeModel, // Model billed, after tier selection
+    pub rental_rate: i128, // Rate applied, after tier selection (basis points)
+    pub rental_fee: i128, // Paid to the lender
+    pub platform_fee: i128, // Kept by the platform
+    pub total_fee: i128, // Charged to the borrower
+}
+
+/// Check a fee model and its base rate. Tiers must be listed by strictly
+/// increasing duration.
+pub(crate) fn validate_fee_model(fee_model: &FeeModel, rental_rate: i128) -> Result<(), Error> {
+    if rental_rate < 0 {
+        return Err(Error::InvalidFeeModel);
+    }
+
+    if let FeeModel::Tiered(tiers) = fee_model {
+        if tiers.is_empty() || tiers.len() > MAX_RATE_TIERS {
+            return Err(Error::InvalidFeeModel);
+        }
+
+        let mut previous: Option<u64> = None;
+        for tier in tiers.iter() {
+            if tier.rental_rate < 0 || previous.is_some_and(|min| tier.min_duration <= min) {
+                return Err(Error::InvalidFeeModel);
+            }
+            previous = Some(tier.min_duration);
+        }
+    }
+
+    Ok(())
+}
+
+/// The model and rate a rental of `duration` is billed at. Tiered pricing
+/// settles on a single daily rate, which later extensions keep.
+fn billing(fee_model: &FeeModel, rental_rate: i128, duration: u64) -> (FeeModel, i128) {
+    match fee_model {
+        FeeModel::Tiered(tiers) => {
+            let rate = tiers
+                .iter()
+                .filter(|tier| tier.min_duration <= duration)
+                .last()
+                .map(|tier| tier.rental_rate)
+                .unwrap_or(rental_rate);
+            (FeeModel::Daily, rate)
+        }
+        model => (model.clone(), rental_rate),
+    }
+}
+
+/// Fees for renting `amount` for `duration` seconds
+pub(crate) fn quote(
+    fee_model: &FeeModel,
+    rental_rate: i128,
+    amount: i128,
+    duration: u64,
+    platform_fee_rate: i128,
+) -> FeeQuote {
+    let (fee_model, rental_rate) = billing(fee_model, rental_rate, duration);
+
+    let rental_fee = match &fee_model {
+        FeeModel::Flat => (amount * rental_rate) / 10000,
+        FeeModel::PerSecond => {
+            (amount * rental_rate * duration as i128) / (10000 * DAY_IN_SECONDS as i128)
+        }
+        _ => {
+            let days = duration.div_ceil(DAY_IN_SECONDS) as i128;
+            (amount * rental_rate * days) / 10000
+        }
+    };
+    let platform_fee = (rental_fee * platform_fee_rate) / 10000;
+
+    FeeQuote {
+        fee_model,
+        rental_rate,
+        rental_fee,
+        platform_fee,
+        total_fee: rental_fee + platform_fee,
+    }
+}
+
+/// Fees for extending a rental of `duration` seconds, billed at `fee_model`
+/// and `rental_rate`, by `extra_duration` seconds. Daily billing charges only
+/// the days the extension starts, so the whole term costs what a single rental
+/// of that length would. Flat fees already cover any duration.
+pub(crate) fn extension_quote(
+    fee_model: &FeeModel,
+    rental_rate: i128,
+    amount: i128,
+    duration: u64,
+    extra_duration: u64,
+    platform_fee_rate: i128,
+) -> FeeQuote {
+    match fee_model {
+        FeeModel::Flat => quote(fee_model, rental_rate, 0, extra_duration, platform_fee_rate),
+        FeeModel::PerSecond => quote(fee_model, rental_rate, amount, extra_duration, platform_fee_rate),
+        _ => {
+            let started_days = (duration + extra_duration).div_ceil(DAY_IN_SECONDS)
+                - duration.div_ceil(DAY_IN_SECONDS);
+            quote(fee_model, rental_rate, amount, started_days * DAY_IN_SECONDS, platform_fee_rate)
+        }
+    }
+}
@@ -1,22 +1,75 @@
 #![cfg(test)]
 
 use super::*;
-use soroban_sdk::{testutils::Address as _, Address, Env};
+use soroban_sdk::{
+    testutils::{storage::{Instance as _, Persistent as _}, Address as _, Ledger as _},
+    vec, Address, Env, Vec,
+};
+
+const DAY: u64 = 24 * 60 * 60;
+
+fn default_config(admin: &Address) -> PlatformConfig {
+    PlatformConfig {
+        admin: admin.clone(),
+        platform_fee_rate: 250, // 2.5% platform fee
+        min_collateral_rate: 1000, // 10% minimum collateral
+        max_rental_duration: 365 * DAY, // 1 year max rental
+        default_grace_period: 24 * 60 * 60, // 1 day to return before default
+        max_late_policy: LatePolicy {
+            grace_period: 7 * 24 * 60 * 60, // Up to a week of grace
+            daily_penalty_rate: 2000, // Up to 20% per day late
+            max_penalty_rate: 5000, // Up to half the collateral
+        },
+    }
+}
+
+fn default_token_config(token_address: &Address) -> TokenConfig {
+    TokenConfig {
+        token_address: token_address.clone(),
+        min_amount: 1,
+        min_collateral_rate: None,
+        max_rental_duration: None,
+        platform_fee_rate: None,
+        maintenance_collateral_rate: 800,
+        liquidation_bonus_rate: 500,
+        price_feed: None,
+        max_price_age: None,
+    }
+}
+
+fn support_token(client: &XRentContractClient, token_address: &Address) {
+    client.set_token_config(&default_token_config(token_address));
+}
+
+fn create_oracle<'a>(env: &Env, client: &XRentContractClient) -> mock_oracle::MockOracleClient<'a> {
+    let oracle_id = env.register(mock_oracle::MockOracle, (14u32,));
+    client.set_oracle(&OracleConfig { oracle: oracle_id.clone(), max_price_age: 300 });
+    mock_oracle::MockOracleClient::new(env, &oracle_id)
+}
+
+fn set_price(env: &Env, oracle: &mock_oracle::MockOracleClient, token_address: &Address, price: i128) {
+    let asset = mock_oracle::Asset::Stellar(token_address.clone());
+    oracle.set_price(&asset, &price, &env.ledger().timestamp());
+}
+
+fn create_contract<'a>(env: &Env, admin: &Address) -> XRentContractClient<'a> {
+    let contract_id = env.register(XRentContract, (default_config(admin),));
+    XRentContractClient::new(env, &contract_id)
+}
 
 #[test]
 fn test_initialize_contract() {
     let env = Env::default();
-    let contract_id = env.register(XRentContract, ());
-    let client = XRentContractClient::new(&env, &contract_id);
+    env.mock_all_auths();
 
     let admin = Address::generate(&env);
-    client.initialize(&admin);
+    let client = create_contract(&env, &admin);
 
     let config = client.get_config().unwrap();
     assert_eq!(config.admin, admin);
     assert_eq!(config.platform_fee_rate, 250);
     assert_eq!(config.min_collateral_rate, 1000);
-    assert_eq!(config.max_rental_duration, 365);
+    assert_eq!(config.max_rental_duration, 365 * DAY);
 }
 
 #[test]
@@ -24,19 +77,17 @@ fn test_create_listing() {
     let env = Env::default();
     env.mock_all_auths();
     
-    let contract_id = env.register(XRentContract, ());
-    let client = XRentContractClient::new(&env, &contract_id);
-
     let admin = Address::generate(&env);
     let lender = Address::generate(&env);
     
-    // Initialize contract
-    client.initialize(&admin);
+    // Deploy contract
+    let client = create_contract(&env, &admin);
 
     // Create a mock token
     let token_admin = Address::generate(&env);
     let token_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
     let token_id = token_contract.address();
+    support_token(&client, &token_id);
     let token_client = token::StellarAssetClient::new(&env, &token_id);
     
     // Mint tokens to lender
@@ -48,9 +99,11 @@ fn test_create_listing() {
         &token_id,
         &100,  // amount
         &500,  // rental rate (5% per day)
-        &1,    // min duration
-        &30,   // max duration
-        &1500  // collateral rate (15%)
+        &DAY,        // min duration
+        &(30 * DAY), // max duration
+        &1500, // collateral rate (15%)
+        &vec![&env, token_id.clone()], // collateral tokens
+        &ListingOptions::default() // one-off listing
     );
 
     assert_eq!(listing_id, 1);
@@ -62,7 +115,7 @@ fn test_create_listing() {
     assert!(listing.is_available);
 
     // Check user listings
-    let user_listings = client.get_user_listings(&lender);
+    let user_listings = client.get_user_listings(&lender, &0);
     assert_eq!(user_listings.len(), 1);
     assert_eq!(user_listings.get(0).unwrap(), listing_id);
 }
@@ -72,20 +125,18 @@ fn test_rent_tokens() {
     let env = Env::default();
     env.mock_all_auths();
     
-    let contract_id = env.register(XRentContract, ());
-    let client = XRentContractClient::new(&env, &contract_id);
-
     let admin = Address::generate(&env);
     let lender = Address::generate(&env);
     let borrower = Address::generate(&env);
     
-    // Initialize contract
-    client.initialize(&admin);
+    // Deploy contract
+    let client = create_contract(&env, &admin);
 
     // Create a mock token
     let token_admin = Address::generate(&env);
     let token_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
     let token_id = token_contract.address();
+    support_token(&client, &token_id);
     let token_client = token::StellarAssetClient::new(&env, &token_id);
     
     // Mint tokens
@@ -98,16 +149,20 @@ fn test_rent_tokens() {
         &token_id,
         &100,  // amount
         &500,  // rental rate (5% per day)
-        &1,    // min duration
-        &30,   // max duration
-        &1500  // collateral rate (15%)
+        &DAY,        // min duration
+        &(30 * DAY), // max duration
+        &1500, // collateral rate (15%)
+        &vec![&env, token_id.clone()], // collateral tokens
+        &ListingOptions::default() // one-off listing
     );
 
     // Rent tokens
     let rental_id = client.rent_tokens(
         &borrower,
         &listing_id,
-        &7  // 7 days
+        &100, // amount
+        &(7 * DAY), // 7 days
+        &token_id // collateral token
     );
 
     assert_eq!(rental_id, 1);
@@ -116,15 +171,15 @@ fn test_rent_tokens() {
     assert_eq!(rental.borrower, borrower);
     assert_eq!(rental.lender, lender);
     assert_eq!(rental.amount, 100);
-    assert!(rental.is_active);
-    assert!(!rental.is_completed);
+    assert_eq!(rental.status, RentalStatus::Active);
+    assert_eq!(rental.settled_at, 0);
 
     // Check that listing is no longer available
     let listing = client.get_listing(&listing_id).unwrap();
     assert!(!listing.is_available);
 
     // Check user rentals
-    let user_rentals = client.get_user_rentals(&borrower);
+    let user_rentals = client.get_user_rentals(&borrower, &0);
     assert_eq!(user_rentals.len(), 1);
     assert_eq!(user_rentals.get(0).unwrap(), rental_id);
 }
@@ -134,20 +189,18 @@ fn test_return_tokens() {
     let env = Env::default();
     env.mock_all_auths();
     
-    let contract_id = env.register(XRentContract, ());
-    let client = XRentContractClient::new(&env, &contract_id);
-
     let admin = Address::generate(&env);
     let lender = Address::generate(&env);
     let borrower = Address::generate(&env);
     
-    // Initialize contract
-    client.initialize(&admin);
+    // Deploy contract
+    let client = create_contract(&env, &admin);
 
     // Create a mock token
     let token_admin = Address::generate(&env);
     let token_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
     let token_id = token_contract.address();
+    support_token(&client, &token_id);
     let token_client = token::StellarAssetClient::new(&env, &token_id);
     
     // Mint tokens
@@ -156,17 +209,19 @@ fn test_return_tokens() {
 
     // Create listing and rent tokens
     let listing_id = client.create_listing(
-        &lender, &token_id, &100, &500, &1, &30, &1500
+        &lender, &token_id, &100, &500, &DAY, &(30 * DAY), &1500, &vec![&env, token_id.clone()], &ListingOptions::default()
     );
     
-    let rental_id = client.rent_tokens(&borrower, &listing_id, &7);
+    let rental_id = client.rent_tokens(&borrower, &listing_id, &100, &(7 * DAY), &token_id);
 
     // Return tokens
     client.return_tokens(&borrower, &rental_id);
 
     let rental = client.get_rental(&rental_id).unwrap();
-    assert!(!rental.is_active);
-    assert!(rental.is_completed);
+    assert_eq!(rental.status, RentalStatus::Returned);
+    assert_eq!(rental.settled_at, env.ledger().timestamp());
+    assert_eq!(rental.penalty_paid, 0);
+    assert_eq!(rental.collateral_refunded, 15);
 }
 
 #[test]
@@ -174,19 +229,17 @@ fn test_cancel_listing() {
     let env = Env::default();
     env.mock_all_auths();
     
-    let contract_id = env.register(XRentContract, ());
-    let client = XRentContractClient::new(&env, &contract_id);
-
     let admin = Address::generate(&env);
     let lender = Address::generate(&env);
     
-    // Initialize contract
-    client.initialize(&admin);
+    // Deploy contract
+    let client = create_contract(&env, &admin);
 
     // Create a mock token
     let token_admin = Address::generate(&env);
     let token_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
     let token_id = token_contract.address();
+    support_token(&client, &token_id);
     let token_client = token::StellarAssetClient::new(&env, &token_id);
     
     // Mint tokens to lender
@@ -194,7 +247,7 @@ fn test_cancel_listing() {
 
     // Create listing
     let listing_id = client.create_listing(
-        &lender, &token_id, &100, &500, &1, &30, &1500
+        &lender, &token_id, &100, &500, &DAY, &(30 * DAY), &1500, &vec![&env, token_id.clone()], &ListingOptions::default()
     );
 
     // Cancel listing
@@ -205,37 +258,1906 @@ fn test_cancel_listing() {
 }
 
 #[test]
-#[should_panic(expected = "Already initialized")]
-fn test_double_initialization() {
+fn test_partial_fills() {
     let env = Env::default();
-    let contract_id = env.register(XRentContract, ());
-    let client = XRentContractClient::new(&env, &contract_id);
+    env.mock_all_auths();
 
     let admin = Address::generate(&env);
-    client.initialize(&admin);
-    
-    // This should panic
-    client.initialize(&admin);
+    let lender = Address::generate(&env);
+    let borrower1 = Address::generate(&env);
+    let borrower2 = Address::generate(&env);
+
+    let client = create_contract(&env, &admin);
+
+    let token_admin = Address::generate(&env);
+    let token_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let token_id = token_contract.address();
+    let mut token_config = default_token_config(&token_id);
+    token_config.min_amount = 1000;
+    client.set_token_config(&token_config);
+    let token_client = token::StellarAssetClient::new(&env, &token_id);
+    let token = token::Client::new(&env, &token_id);
+
+    token_client.mint(&lender, &10000);
+    token_client.mint(&borrower1, &1000);
+    token_client.mint(&borrower2, &1000);
+
+    // 1% per day, 10% collateral
+    let listing_id = client.create_listing(
+        &lender, &token_id, &10000, &100, &DAY, &(30 * DAY), &1000, &vec![&env, token_id.clone()], &ListingOptions::default()
+    );
+
+    // Fees and collateral are charged on the portion taken
+    let rental1 = client.rent_tokens(&borrower1, &listing_id, &4000, &(5 * DAY), &token_id);
+    let rental = client.get_rental(&rental1).unwrap();
+    assert_eq!(rental.listing_id, listing_id);
+    assert_eq!(rental.amount, 4000);
+    assert_eq!(rental.rental_fee, 200);
+    assert_eq!(rental.collateral_amount, 400);
+    assert_eq!(token.balance(&borrower1), 1000 + 4000 - 205 - 400);
+
+    let listing = client.get_listing(&listing_id).unwrap();
+    assert_eq!(listing.amount, 10000);
+    assert_eq!(listing.available_amount, 6000);
+    assert!(listing.is_available);
+
+    // Requests must be positive, fit in what's left and meet the token minimum
+    assert_eq!(
+        client.try_rent_tokens(&borrower2, &listing_id, &0, &(2 * DAY), &token_id),
+        Err(Ok(Error::InvalidAmount))
+    );
+    assert_eq!(
+        client.try_rent_tokens(&borrower2, &listing_id, &6001, &(2 * DAY), &token_id),
+        Err(Ok(Error::InsufficientBalance))
+    );
+    assert_eq!(
+        client.try_rent_tokens(&borrower2, &listing_id, &999, &(2 * DAY), &token_id),
+        Err(Ok(Error::InvalidAmount))
+    );
+
+    let rental2 = client.rent_tokens(&borrower2, &listing_id, &3000, &(2 * DAY), &token_id);
+    assert_eq!(client.get_rental(&rental2).unwrap().rental_fee, 60);
+    assert_eq!(client.get_listing(&listing_id).unwrap().available_amount, 3000);
+    assert_eq!(token.balance(&lender), 200 + 60);
+
+    // Cancelling refunds only the undrawn remainder
+    client.cancel_listing(&lender, &listing_id);
+    let listing = client.get_listing(&listing_id).unwrap();
+    assert_eq!(listing.available_amount, 0);
+    assert!(!listing.is_available);
+    assert_eq!(token.balance(&lender), 260 + 3000);
+
+    // Outstanding rentals still return principal to the lender
+    client.return_tokens(&borrower1, &rental1);
+    assert_eq!(token.balance(&lender), 3260 + 4000);
+    assert_eq!(client.get_rental(&rental2).unwrap().status, RentalStatus::Active);
 }
 
 #[test]
-#[should_panic(expected = "Invalid amount")]
-fn test_invalid_amount() {
+fn test_standing_listing() {
     let env = Env::default();
     env.mock_all_auths();
-    
-    let contract_id = env.register(XRentContract, ());
-    let client = XRentContractClient::new(&env, &contract_id);
 
     let admin = Address::generate(&env);
     let lender = Address::generate(&env);
-    
-    client.initialize(&admin);
+    let borrower = Address::generate(&env);
+
+    let client = create_contract(&env, &admin);
+
+    let token_admin = Address::generate(&env);
+    let token_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let token_id = token_contract.address();
+    support_token(&client, &token_id);
+    let token_client = token::StellarAssetClient::new(&env, &token_id);
+    let token = token::Client::new(&env, &token_id);
+
+    token_client.mint(&lender, &2000);
+    token_client.mint(&borrower, &1000);
+
+    env.ledger().with_mut(|li| li.timestamp = 1_000_000);
+    let expires_at = env.ledger().timestamp() + 20 * DAY;
+    let options = ListingOptions { standing: true, expires_at, ..Default::default() };
+
+    // Expiry has to be in the future
+    assert_eq!(
+        client.try_create_listing(
+            &lender, &token_id, &1000, &100, &DAY, &(30 * DAY), &1000, &vec![&env, token_id.clone()],
+            &ListingOptions { standing: true, expires_at: env.ledger().timestamp(), ..Default::default() }
+        ),
+        Err(Ok(Error::InvalidDuration))
+    );
+
+    let listing_id = client.create_listing(
+        &lender, &token_id, &1000, &100, &DAY, &(30 * DAY), &1000, &vec![&env, token_id.clone()], &options
+    );
+
+    let rental_id = client.rent_tokens(&borrower, &listing_id, &1000, &(5 * DAY), &token_id);
+    assert!(!client.get_listing(&listing_id).unwrap().is_available);
+
+    // Returned principal goes back into escrow and the listing reopens
+    client.return_tokens(&borrower, &rental_id);
+    let listing = client.get_listing(&listing_id).unwrap();
+    assert!(listing.is_available);
+    assert_eq!(listing.available_amount, 1000);
+    assert_eq!(token.balance(&lender), 1000 + 50);
+
+    // Once expired the listing takes no rentals and returns pay the lender
+    let rental_id = client.rent_tokens(&borrower, &listing_id, &1000, &(5 * DAY), &token_id);
+    env.ledger().with_mut(|li| li.timestamp = expires_at);
+    client.return_tokens(&borrower, &rental_id);
+    let listing = client.get_listing(&listing_id).unwrap();
+    assert!(!listing.is_available);
+    assert_eq!(listing.available_amount, 0);
+    assert_eq!(token.balance(&lender), 2000 + 100 + 10); // principal, fees, late penalty
+
+    // Cancelling a fully drawn standing listing stops it reopening
+    let listing_id = client.create_listing(
+        &lender, &token_id, &1000, &100, &DAY, &(30 * DAY), &1000, &vec![&env, token_id.clone()],
+        &ListingOptions { standing: true, ..Default::default() }
+    );
+    let rental_id = client.rent_tokens(&borrower, &listing_id, &1000, &(5 * DAY), &token_id);
+    client.cancel_listing(&lender, &listing_id);
+    assert_eq!(
+        client.try_cancel_listing(&lender, &listing_id),
+        Err(Ok(Error::ListingNotAvailable))
+    );
+
+    client.return_tokens(&borrower, &rental_id);
+    let listing = client.get_listing(&listing_id).unwrap();
+    assert!(!listing.is_available);
+    assert!(!listing.options.standing);
+    assert_eq!(token.balance(&lender), 2000 + 150 + 10);
+    assert_eq!(
+        client.try_rent_tokens(&borrower, &listing_id, &1000, &(5 * DAY), &token_id),
+        Err(Ok(Error::ListingNotAvailable))
+    );
+}
+
+#[test]
+fn test_extend_rental() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let lender = Address::generate(&env);
+    let borrower = Address::generate(&env);
+    let stranger = Address::generate(&env);
+
+    let client = create_contract(&env, &admin);
+
+    let token_admin = Address::generate(&env);
+    let token_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let token_id = token_contract.address();
+    support_token(&client, &token_id);
+    let token_client = token::StellarAssetClient::new(&env, &token_id);
+    let token = token::Client::new(&env, &token_id);
+
+    token_client.mint(&lender, &20000);
+    token_client.mint(&borrower, &5000);
+
+    // 1% per day, up to 10 days
+    let listing_id = client.create_listing(
+        &lender, &token_id, &10000, &100, &DAY, &(10 * DAY), &1000, &vec![&env, token_id.clone()], &ListingOptions::default()
+    );
+    let rental_id = client.rent_tokens(&borrower, &listing_id, &10000, &(5 * DAY), &token_id);
+    let rental = client.get_rental(&rental_id).unwrap();
+    assert_eq!(rental.rental_rate, 100);
+    assert_eq!(rental.platform_fee_rate, 250);
+
+    // Only the borrower can extend, and not past the listing's maximum
+    assert_eq!(
+        client.try_extend_rental(&stranger, &rental_id, &(2 * DAY)),
+        Err(Ok(Error::UnauthorizedReturn))
+    );
+    assert_eq!(
+        client.try_extend_rental(&borrower, &rental_id, &(6 * DAY)),
+        Err(Ok(Error::InvalidDuration))
+    );
+    assert_eq!(
+        client.try_extend_rental(&borrower, &rental_id, &0),
+        Err(Ok(Error::InvalidDuration))
+    );
+    assert_eq!(
+        client.try_extend_rental(&borrower, &rental_id, &u64::MAX),
+        Err(Ok(Error::InvalidDuration))
+    );
+
+    // Extra days are charged at the original rates even after they change
+    client.set_platform_fee_rate(&500);
+    let borrower_balance = token.balance(&borrower);
+    let lender_balance = token.balance(&lender);
+    client.extend_rental(&borrower, &rental_id, &(3 * DAY));
+
+    let extended = client.get_rental(&rental_id).unwrap();
+    assert_eq!(extended.end_time, rental.end_time + 3 * DAY);
+    assert_eq!(extended.rental_fee, 500 + 300);
+    assert_eq!(token.balance(&borrower), borrower_balance - 300 - 7);
+    assert_eq!(token.balance(&lender), lender_balance + 300);
+    assert_eq!(client.get_accrued_fees(&token_id), 12 + 7);
+
+    // Extensions only pay for the days they start
+    let borrower_balance = token.balance(&borrower);
+    client.extend_rental(&borrower, &rental_id, &(60 * 60));
+    client.extend_rental(&borrower, &rental_id, &(60 * 60));
+    let extended = client.get_rental(&rental_id).unwrap();
+    assert_eq!(extended.end_time, rental.end_time + 3 * DAY + 2 * 60 * 60);
+    assert_eq!(extended.rental_fee, 500 + 300 + 100);
+    assert_eq!(token.balance(&borrower), borrower_balance - 100 - 2);
+
+    // The platform-wide maximum applies as well
+    client.set_max_rental_duration(&(8 * DAY));
+    assert_eq!(
+        client.try_extend_rental(&borrower, &rental_id, &DAY),
+        Err(Ok(Error::InvalidDuration))
+    );
+
+    // Expired rentals can't be extended
+    env.ledger().with_mut(|li| li.timestamp = extended.end_time + 1);
+    assert_eq!(
+        client.try_extend_rental(&borrower, &rental_id, &DAY),
+        Err(Ok(Error::RentalExpired))
+    );
+
+    // Lenders can opt out of extensions
+    let fixed_id = client.create_listing(
+        &lender, &token_id, &1000, &100, &DAY, &(8 * DAY), &1000, &vec![&env, token_id.clone()],
+        &ListingOptions { fixed_term: true, ..Default::default() }
+    );
+    let fixed_rental = client.rent_tokens(&borrower, &fixed_id, &1000, &(2 * DAY), &token_id);
+    assert_eq!(
+        client.try_extend_rental(&borrower, &fixed_rental, &DAY),
+        Err(Ok(Error::ListingNotAvailable))
+    );
+}
+
+#[test]
+fn test_fee_escrow() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let lender = Address::generate(&env);
+    let borrower = Address::generate(&env);
+
+    let client = create_contract(&env, &admin);
+
+    let token_admin = Address::generate(&env);
+    let token_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let token_id = token_contract.address();
+    support_token(&client, &token_id);
+    let token_client = token::StellarAssetClient::new(&env, &token_id);
+    let token = token::Client::new(&env, &token_id);
+
+    token_client.mint(&lender, &20000);
+    token_client.mint(&borrower, &5000);
+
+    let escrowed = ListingOptions { escrow_fees: true, ..Default::default() };
+
+    // 1% per day for 10 days: 1000 fee held by the contract
+    let listing_id = client.create_listing(
+        &lender, &token_id, &10000, &100, &DAY, &(30 * DAY), &1000, &vec![&env, token_id.clone()], &escrowed
+    );
+    let rental_id = client.rent_tokens(&borrower, &listing_id, &10000, &(10 * DAY), &token_id);
+    assert_eq!(token.balance(&lender), 10000);
+    let rental = client.get_rental(&rental_id).unwrap();
+    assert!(rental.fee_escrowed);
+    assert_eq!(rental.fees_claimed, 0);
+
+    // Lenders collect fees as they accrue
+    env.ledger().with_mut(|li| li.timestamp = rental.start_time + 4 * DAY);
+    assert_eq!(client.claim_earned_fees(&rental_id), 400);
+    assert_eq!(client.claim_earned_fees(&rental_id), 0);
+    assert_eq!(token.balance(&lender), 10000 + 400);
+
+    // Returning early refunds the unearned part
+    env.ledger().with_mut(|li| li.timestamp = rental.start_time + 6 * DAY);
+    let borrower_balance = token.balance(&borrower);
+    client.return_tokens(&borrower, &rental_id);
+    let rental = client.get_rental(&rental_id).unwrap();
+    assert_eq!(rental.fees_claimed, 600);
+    assert_eq!(rental.fee_refunded, 400);
+    assert_eq!(token.balance(&lender), 20000 + 600);
+    assert_eq!(token.balance(&borrower), borrower_balance - 10000 + 1000 + 400);
+    assert_eq!(
+        client.try_claim_earned_fees(&rental_id),
+        Err(Ok(Error::RentalExpired))
+    );
+
+    // Defaulting forfeits the whole fee to the lender
+    let listing_id = client.create_listing(
+        &lender, &token_id, &1000, &100, &DAY, &(30 * DAY), &1000, &vec![&env, token_id.clone()], &escrowed
+    );
+    let rental_id = client.rent_tokens(&borrower, &listing_id, &1000, &(10 * DAY), &token_id);
+    let rental = client.get_rental(&rental_id).unwrap();
+    env.ledger().with_mut(|li| li.timestamp = rental.end_time + 2 * DAY);
+    let lender_balance = token.balance(&lender);
+    client.settle_default(&rental_id);
+    assert_eq!(token.balance(&lender), lender_balance + 100 + 100);
+    assert_eq!(client.get_rental(&rental_id).unwrap().fees_claimed, 100);
+
+    // Fees paid up front leave nothing to claim
+    let listing_id = client.create_listing(
+        &lender, &token_id, &1000, &100, &DAY, &(30 * DAY), &1000, &vec![&env, token_id.clone()], &ListingOptions::default()
+    );
+    let rental_id = client.rent_tokens(&borrower, &listing_id, &1000, &(10 * DAY), &token_id);
+    assert_eq!(client.get_rental(&rental_id).unwrap().fees_claimed, 100);
+    assert_eq!(client.claim_earned_fees(&rental_id), 0);
+
+    // Claimed fees aren't refunded again after an extension slows accrual
+    let listing_id = client.create_listing(
+        &lender, &token_id, &10000, &100, &DAY, &(30 * DAY), &1000, &vec![&env, token_id.clone()], &escrowed
+    );
+    let rental_id = client.rent_tokens(&borrower, &listing_id, &10000, &(DAY + DAY / 2), &token_id);
+    let rental = client.get_rental(&rental_id).unwrap();
+    env.ledger().with_mut(|li| li.timestamp = rental.start_time + DAY);
+    assert_eq!(client.claim_earned_fees(&rental_id), 133);
+    client.extend_rental(&borrower, &rental_id, &DAY);
+    assert_eq!(client.get_rental(&rental_id).unwrap().rental_fee, 300);
+    assert_eq!(client.quote_return(&rental_id, &(rental.start_time + DAY)).fee_refund, 167);
+    client.return_tokens(&borrower, &rental_id);
+    let rental = client.get_rental(&rental_id).unwrap();
+    assert_eq!(rental.fees_claimed, 133);
+    assert_eq!(rental.fee_refunded, 167);
+}
+
+#[test]
+fn test_return_partial() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let lender = Address::generate(&env);
+    let borrower = Address::generate(&env);
+
+    let client = create_contract(&env, &admin);
+
+    let token_admin = Address::generate(&env);
+    let token_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let token_id = token_contract.address();
+    support_token(&client, &token_id);
+    let token_client = token::StellarAssetClient::new(&env, &token_id);
+    let token = token::Client::new(&env, &token_id);
+
+    token_client.mint(&lender, &1000);
+    token_client.mint(&borrower, &1000);
+
+    let listing_id = client.create_listing(
+        &lender, &token_id, &1000, &100, &DAY, &(30 * DAY), &1500, &vec![&env, token_id.clone()], &ListingOptions::default()
+    );
+    let rental_id = client.rent_tokens(&borrower, &listing_id, &1000, &(5 * DAY), &token_id);
+    assert_eq!(client.get_rental(&rental_id).unwrap().collateral_amount, 150);
+
+    // Only part of the outstanding principal can be returned this way
+    assert_eq!(
+        client.try_return_partial(&borrower, &rental_id, &0),
+        Err(Ok(Error::InvalidAmount))
+    );
+    assert_eq!(
+        client.try_return_partial(&borrower, &rental_id, &1000),
+        Err(Ok(Error::InvalidAmount))
+    );
+
+    // Collateral is released in proportion to the principal returned
+    let lender_balance = token.balance(&lender);
+    let borrower_balance = token.balance(&borrower);
+    client.return_partial(&borrower, &rental_id, &400);
+
+    let rental = client.get_rental(&rental_id).unwrap();
+    assert_eq!(rental.amount, 600);
+    assert_eq!(rental.amount_returned, 400);
+    assert_eq!(rental.collateral_amount, 90);
+    assert_eq!(rental.collateral_refunded, 60);
+    assert_eq!(rental.status, RentalStatus::Active);
+    assert_eq!(token.balance(&lender), lender_balance + 400);
+    assert_eq!(token.balance(&borrower), borrower_balance - 400 + 60);
+
+    // After expiry only a full return is possible, and the late penalty
+    // applies to the remaining collateral
+    env.ledger().with_mut(|li| li.timestamp = rental.end_time + 1);
+    assert_eq!(
+        client.try_return_partial(&borrower, &rental_id, &100),
+        Err(Ok(Error::RentalExpired))
+    );
+    client.return_tokens(&borrower, &rental_id);
+
+    let rental = client.get_rental(&rental_id).unwrap();
+    assert_eq!(rental.status, RentalStatus::ReturnedLate);
+    assert_eq!(rental.penalty_paid, 9);
+    assert_eq!(rental.collateral_refunded, 60 + 81);
+    assert_eq!(token.balance(&lender), lender_balance + 1000 + 9);
+}
+
+#[test]
+fn test_late_policy() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let lender = Address::generate(&env);
+    let borrower = Address::generate(&env);
+
+    let client = create_contract(&env, &admin);
+
+    let token_admin = Address::generate(&env);
+    let token_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let token_id = token_contract.address();
+    support_token(&client, &token_id);
+    let token_client = token::StellarAssetClient::new(&env, &token_id);
+
+    token_client.mint(&lender, &10000);
+    token_client.mint(&borrower, &10000);
+
+    let late_policy = LatePolicy {
+        grace_period: DAY,
+        daily_penalty_rate: 500, // 5% per day
+        max_penalty_rate: 2000, // At most 20%
+    };
+    let options = ListingOptions { late_policy: late_policy.clone(), ..Default::default() };
+
+    // Policies must stay within the platform bounds
+    assert_eq!(
+        client.try_create_listing(
+            &lender, &token_id, &1000, &100, &DAY, &(30 * DAY), &1000, &vec![&env, token_id.clone()],
+            &ListingOptions {
+                late_policy: LatePolicy { max_penalty_rate: 6000, ..late_policy.clone() },
+                ..Default::default()
+            }
+        ),
+        Err(Ok(Error::InvalidLatePolicy))
+    );
+    assert_eq!(
+        client.try_set_max_late_policy(&LatePolicy { max_penalty_rate: 10001, ..late_policy.clone() }),
+        Err(Ok(Error::InvalidConfig))
+    );
+
+    // Each rental carries the listing's policy; collateral is 100 per rental
+    let mut rental_ids = Vec::new(&env);
+    for _ in 0..3 {
+        let listing_id = client.create_listing(
+            &lender, &token_id, &1000, &100, &DAY, &(30 * DAY), &1000, &vec![&env, token_id.clone()], &options
+        );
+        rental_ids.push_back(client.rent_tokens(&borrower, &listing_id, &1000, &(5 * DAY), &token_id));
+    }
+    let rental = client.get_rental(&rental_ids.get(0).unwrap()).unwrap();
+    assert_eq!(rental.late_policy, late_policy);
+
+    // Late but within the grace period: no penalty
+    env.ledger().with_mut(|li| li.timestamp = rental.end_time + DAY);
+    client.return_tokens(&borrower, &rental_ids.get(0).unwrap());
+    let settled = client.get_rental(&rental_ids.get(0).unwrap()).unwrap();
+    assert_eq!(settled.status, RentalStatus::ReturnedLate);
+    assert_eq!(settled.penalty_paid, 0);
+
+    // Any started day past the grace period counts
+    env.ledger().with_mut(|li| li.timestamp = rental.end_time + DAY + 1);
+    client.return_tokens(&borrower, &rental_ids.get(1).unwrap());
+    assert_eq!(client.get_rental(&rental_ids.get(1).unwrap()).unwrap().penalty_paid, 5);
+
+    // The penalty stops growing at the cap
+    env.ledger().with_mut(|li| li.timestamp = rental.end_time + DAY + 10 * DAY);
+    client.return_tokens(&borrower, &rental_ids.get(2).unwrap());
+    let settled = client.get_rental(&rental_ids.get(2).unwrap()).unwrap();
+    assert_eq!(settled.penalty_paid, 20);
+    assert_eq!(settled.collateral_refunded, 80);
+
+    // A listing grace period longer than the platform's also delays defaults
+    let long_grace = ListingOptions {
+        late_policy: LatePolicy { grace_period: 7 * DAY, ..late_policy.clone() },
+        ..Default::default()
+    };
+    let listing_id = client.create_listing(
+        &lender, &token_id, &1000, &100, &DAY, &(30 * DAY), &1000, &vec![&env, token_id.clone()], &long_grace
+    );
+    let rental_id = client.rent_tokens(&borrower, &listing_id, &1000, &(5 * DAY), &token_id);
+    let end_time = client.get_rental(&rental_id).unwrap().end_time;
+
+    env.ledger().with_mut(|li| li.timestamp = end_time + 2 * DAY);
+    assert_eq!(client.quote_return(&rental_id, &(end_time + 2 * DAY)).penalty, 0);
+    assert_eq!(client.try_settle_default(&rental_id), Err(Ok(Error::RentalActive)));
+
+    env.ledger().with_mut(|li| li.timestamp = end_time + 7 * DAY + 1);
+    client.settle_default(&rental_id);
+    assert_eq!(client.get_rental(&rental_id).unwrap().status, RentalStatus::Defaulted);
+}
+
+#[test]
+fn test_fee_models() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let lender = Address::generate(&env);
+    let borrower = Address::generate(&env);
+
+    let client = create_contract(&env, &admin);
+
+    let token_admin = Address::generate(&env);
+    let token_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let token_id = token_contract.address();
+    support_token(&client, &token_id);
+    let token_client = token::StellarAssetClient::new(&env, &token_id);
+
+    token_client.mint(&lender, &100_000);
+    token_client.mint(&borrower, &100_000);
+
+    let list = |rate: i128, fee_model: FeeModel| {
+        client.create_listing(
+            &lender, &token_id, &10000, &rate, &0, &(30 * DAY), &1000, &vec![&env, token_id.clone()],
+            &ListingOptions { fee_model, ..Default::default() }
+        )
+    };
+
+    // Daily: every started day is charged
+    let daily = list(100, FeeModel::Daily);
+    assert_eq!(client.quote_rental(&daily, &10000, &(DAY + 1), &token_id).rental_fee, 200);
+
+    // Per second: 1% a day for six hours
+    let per_second = list(100, FeeModel::PerSecond);
+    let quote = client.quote_rental(&per_second, &10000, &(6 * 60 * 60), &token_id);
+    assert_eq!(quote.rental_fee, 25);
+    assert_eq!(quote.total_fee, 25);
+
+    // Flat: the same fee whatever the duration
+    let flat = list(300, FeeModel::Flat);
+    assert_eq!(client.quote_rental(&flat, &10000, &DAY, &token_id).rental_fee, 300);
+    assert_eq!(client.quote_rental(&flat, &10000, &(20 * DAY), &token_id).rental_fee, 300);
+
+    // Tiered: longer rentals get the discounted rate
+    let tiers = vec![
+        &env,
+        RateTier { min_duration: 7 * DAY, rental_rate: 80 },
+        RateTier { min_duration: 14 * DAY, rental_rate: 50 },
+    ];
+    let tiered = list(100, FeeModel::Tiered(tiers.clone()));
+    assert_eq!(client.quote_rental(&tiered, &10000, &(3 * DAY), &token_id).rental_rate, 100);
+    assert_eq!(client.quote_rental(&tiered, &10000, &(7 * DAY), &token_id).rental_fee, 560);
+    let quote = client.quote_rental(&tiered, &10000, &(20 * DAY), &token_id);
+    assert_eq!(quote.fee_model, FeeModel::Daily);
+    assert_eq!(quote.rental_rate, 50);
+    assert_eq!(quote.rental_fee, 1000);
+    assert_eq!(quote.platform_fee, 25);
+
+    // Rentals charge exactly what was quoted and keep the tier rate
+    let rental_id = client.rent_tokens(&borrower, &tiered, &10000, &(20 * DAY), &token_id);
+    let rental = client.get_rental(&rental_id).unwrap();
+    assert_eq!(rental.rental_fee, 1000);
+    assert_eq!(rental.fee_model, FeeModel::Daily);
+    assert_eq!(rental.rental_rate, 50);
+
+    let rental_id = client.rent_tokens(&borrower, &per_second, &10000, &(6 * 60 * 60), &token_id);
+    assert_eq!(client.get_rental(&rental_id).unwrap().rental_fee, 25);
+
+    // Extending a flat rental costs nothing more
+    let rental_id = client.rent_tokens(&borrower, &flat, &10000, &DAY, &token_id);
+    client.extend_rental(&borrower, &rental_id, &DAY);
+    assert_eq!(client.get_rental(&rental_id).unwrap().rental_fee, 300);
+
+    // Tiers must be ordered and rates non-negative
+    let unordered = vec![
+        &env,
+        RateTier { min_duration: 14 * DAY, rental_rate: 50 },
+        RateTier { min_duration: 7 * DAY, rental_rate: 80 },
+    ];
+    assert_eq!(
+        client.try_create_listing(
+            &lender, &token_id, &10000, &100, &0, &(30 * DAY), &1000, &vec![&env, token_id.clone()],
+            &ListingOptions { fee_model: FeeModel::Tiered(unordered), ..Default::default() }
+        ),
+        Err(Ok(Error::InvalidFeeModel))
+    );
+    assert_eq!(
+        client.try_create_listing(
+            &lender, &token_id, &10000, &-1, &0, &(30 * DAY), &1000, &vec![&env, token_id.clone()],
+            &ListingOptions::default()
+        ),
+        Err(Ok(Error::InvalidFeeModel))
+    );
+}
+
+#[test]
+fn test_quotes() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let lender = Address::generate(&env);
+    let borrower = Address::generate(&env);
+
+    let client = create_contract(&env, &admin);
 
     let token_admin = Address::generate(&env);
     let token_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
     let token_id = token_contract.address();
+    support_token(&client, &token_id);
+    let token_client = token::StellarAssetClient::new(&env, &token_id);
+    let token = token::Client::new(&env, &token_id);
+
+    token_client.mint(&lender, &10000);
+    token_client.mint(&borrower, &10000);
+
+    let options = ListingOptions { escrow_fees: true, ..Default::default() };
+    let listing_id = client.create_listing(
+        &lender, &token_id, &10000, &100, &DAY, &(30 * DAY), &1000, &vec![&env, token_id.clone()], &options
+    );
+
+    // Quotes fail the same way renting would
+    assert_eq!(
+        client.try_quote_rental(&listing_id, &20000, &(10 * DAY), &token_id),
+        Err(Ok(Error::InsufficientBalance))
+    );
+
+    let quote = client.quote_rental(&listing_id, &10000, &(10 * DAY), &token_id);
+    assert_eq!(quote.rental_fee, 1000);
+    assert_eq!(quote.platform_fee, 25);
+    assert_eq!(quote.collateral_amount, 1000);
+    assert_eq!(quote.total_debit, 1000 + 25 + 1000);
+    assert_eq!(quote.end_time, env.ledger().timestamp() + 10 * DAY);
+
+    let balance = token.balance(&borrower);
+    let rental_id = client.rent_tokens(&borrower, &listing_id, &10000, &(10 * DAY), &token_id);
+    assert_eq!(token.balance(&borrower), balance + 10000 - quote.total_debit);
+    let rental = client.get_rental(&rental_id).unwrap();
+    assert_eq!(rental.end_time, quote.end_time);
+
+    // Early return: unearned fee back to the borrower, earned fee to the lender
+    let early = client.quote_return(&rental_id, &(rental.start_time + 4 * DAY));
+    assert_eq!(early.status, RentalStatus::Returned);
+    assert_eq!(early.penalty, 0);
+    assert_eq!(early.collateral_refund, 1000);
+    assert_eq!(early.fee_refund, 600);
+    assert_eq!(early.lender_payout, 10000 + 400);
+    assert!(!early.relisted);
+
+    // Late return: the late policy applies and the whole fee is earned
+    let late = client.quote_return(&rental_id, &(rental.end_time + 1));
+    assert_eq!(late.status, RentalStatus::ReturnedLate);
+    assert_eq!(late.penalty, 100);
+    assert_eq!(late.collateral_refund, 900);
+    assert_eq!(late.fee_refund, 0);
+    assert_eq!(late.lender_payout, 10000 + 1000);
+
+    // Returning at the quoted time settles exactly as quoted
+    env.ledger().with_mut(|li| li.timestamp = rental.start_time + 4 * DAY);
+    let lender_balance = token.balance(&lender);
+    let borrower_balance = token.balance(&borrower);
+    client.return_tokens(&borrower, &rental_id);
+    assert_eq!(token.balance(&lender), lender_balance + early.lender_payout);
+    assert_eq!(
+        token.balance(&borrower),
+        borrower_balance - 10000 + early.collateral_refund + early.fee_refund
+    );
+
+    // Settled rentals can't be quoted
+    assert_eq!(
+        client.try_quote_return(&rental_id, &env.ledger().timestamp()),
+        Err(Ok(Error::RentalExpired))
+    );
+}
+
+#[test]
+fn test_list_available() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let lender = Address::generate(&env);
+    let borrower = Address::generate(&env);
+
+    let client = create_contract(&env, &admin);
+
+    let token_admin = Address::generate(&env);
+    let token_id = env.register_stellar_asset_contract_v2(token_admin.clone()).address();
+    let other_id = env.register_stellar_asset_contract_v2(token_admin.clone()).address();
+    support_token(&client, &token_id);
+    support_token(&client, &other_id);
+    token::StellarAssetClient::new(&env, &token_id).mint(&lender, &100_000);
+    token::StellarAssetClient::new(&env, &token_id).mint(&borrower, &100_000);
+    token::StellarAssetClient::new(&env, &other_id).mint(&lender, &100_000);
+
+    let list = |token: &Address, amount: i128, rate: i128, max_days: u64, standing: bool| {
+        client.create_listing(
+            &lender, token, &amount, &rate, &DAY, &(max_days * DAY), &1000, &vec![&env, token.clone()],
+            &ListingOptions { standing, ..Default::default() }
+        )
+    };
+    let ids = [
+        list(&token_id, 1000, 100, 30, true),
+        list(&token_id, 5000, 300, 7, false),
+        list(&other_id, 2000, 100, 30, false),
+        list(&token_id, 3000, 50, 90, false),
+        list(&token_id, 4000, 200, 30, false),
+    ];
+    let page_ids = |page: &ListingPage| {
+        let mut page_ids = Vec::new(&env);
+        for listing in page.listings.iter() {
+            page_ids.push_back(listing.listing_id);
+        }
+        page_ids
+    };
+
+    // Empty pages would read as the end of the index
+    assert_eq!(
+        client.try_list_available(&0, &0, &ListingFilter::default()),
+        Err(Ok(Error::InvalidPageSize))
+    );
+
+    // Pages follow the order listings opened in
+    let page = client.list_available(&0, &2, &ListingFilter::default());
+    assert_eq!(page_ids(&page), vec![&env, ids[0], ids[1]]);
+    assert_eq!(page.next_cursor, ids[1]);
+    let page = client.list_available(&page.next_cursor, &10, &ListingFilter::default());
+    assert_eq!(page_ids(&page), vec![&env, ids[2], ids[3], ids[4]]);
+    assert_eq!(page.next_cursor, 0);
+
+    // Fully drawn and cancelled listings leave the index; partially drawn ones stay
+    client.rent_tokens(&borrower, &ids[0], &1000, &(5 * DAY), &token_id);
+    client.rent_tokens(&borrower, &ids[3], &1000, &(5 * DAY), &token_id);
+    client.cancel_listing(&lender, &ids[1]);
+    let page = client.list_available(&0, &10, &ListingFilter::default());
+    assert_eq!(page_ids(&page), vec![&env, ids[2], ids[3], ids[4]]);
+
+    // A cursor whose listing has left the index still resumes in order
+    let page = client.list_available(&ids[1], &10, &ListingFilter::default());
+    assert_eq!(page_ids(&page), vec![&env, ids[2], ids[3], ids[4]]);
+
+    // Filters combine
+    let filter = ListingFilter { token: Some(token_id.clone()), ..Default::default() };
+    let page = client.list_available(&0, &10, &filter);
+    assert_eq!(page_ids(&page), vec![&env, ids[3], ids[4]]);
+
+    let filter = ListingFilter { min_amount: Some(2000), max_amount: Some(3000), ..Default::default() };
+    let page = client.list_available(&0, &10, &filter);
+    assert_eq!(page_ids(&page), vec![&env, ids[2], ids[3]]);
+
+    let filter = ListingFilter { max_rate: Some(100), min_duration: Some(60 * DAY), ..Default::default() };
+    let page = client.list_available(&0, &10, &filter);
+    assert_eq!(page_ids(&page), vec![&env, ids[3]]);
+
+    // A standing listing that reopens goes to the back
+    client.return_tokens(&borrower, &1);
+    let page = client.list_available(&0, &10, &ListingFilter::default());
+    assert_eq!(page_ids(&page), vec![&env, ids[2], ids[3], ids[4], ids[0]]);
+}
+
+#[test]
+fn test_rental_indexes() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let lender = Address::generate(&env);
+    let other_lender = Address::generate(&env);
+    let borrower = Address::generate(&env);
+
+    let client = create_contract(&env, &admin);
+
+    let token_admin = Address::generate(&env);
+    let token_id = env.register_stellar_asset_contract_v2(token_admin.clone()).address();
+    support_token(&client, &token_id);
+    let token_client = token::StellarAssetClient::new(&env, &token_id);
+    token_client.mint(&lender, &100_000);
+    token_client.mint(&other_lender, &100_000);
+    token_client.mint(&borrower, &100_000);
+
+    let listing_id = client.create_listing(
+        &lender, &token_id, &10000, &100, &DAY, &(30 * DAY), &1000, &vec![&env, token_id.clone()], &ListingOptions::default()
+    );
+    let other_listing_id = client.create_listing(
+        &other_lender, &token_id, &10000, &100, &DAY, &(30 * DAY), &1000, &vec![&env, token_id.clone()], &ListingOptions::default()
+    );
+
+    let rental1 = client.rent_tokens(&borrower, &listing_id, &1000, &(10 * DAY), &token_id);
+    let rental2 = client.rent_tokens(&borrower, &other_listing_id, &1000, &(3 * DAY), &token_id);
+    let rental3 = client.rent_tokens(&borrower, &listing_id, &1000, &(5 * DAY), &token_id);
+    let rental4 = client.rent_tokens(&borrower, &listing_id, &1000, &(7 * DAY), &token_id);
+    let page_ids = |page: &RentalPage| {
+        let mut page_ids = Vec::new(&env);
+        for rental in page.rentals.iter() {
+            page_ids.push_back(rental.rental_id);
+        }
+        page_ids
+    };
+
+    // Lenders see the rentals outstanding against their listings
+    let page = client.get_lender_rentals(&lender, &0, &2);
+    assert_eq!(page_ids(&page), vec![&env, rental1, rental3]);
+    let page = client.get_lender_rentals(&lender, &page.next_cursor, &2);
+    assert_eq!(page_ids(&page), vec![&env, rental4]);
+    assert_eq!(page.next_cursor, 0);
+    let page = client.get_lender_rentals(&other_lender, &0, &10);
+    assert_eq!(page_ids(&page), vec![&env, rental2]);
+
+    // Nothing is overdue yet; later, rentals come back earliest end first
+    let start = env.ledger().timestamp();
+    assert_eq!(client.get_overdue_rentals(&start, &0, &10).rentals.len(), 0);
+    assert_eq!(
+        client.try_get_overdue_rentals(&(start + 8 * DAY), &0, &0),
+        Err(Ok(Error::InvalidPageSize))
+    );
+    let page = client.get_overdue_rentals(&(start + 8 * DAY), &0, &10);
+    assert_eq!(page_ids(&page), vec![&env, rental2, rental3, rental4]);
+    assert_eq!(page.next_cursor, 0);
+    let page = client.get_overdue_rentals(&(start + 8 * DAY), &0, &1);
+    assert_eq!(page_ids(&page), vec![&env, rental2]);
+    let page = client.get_overdue_rentals(&(start + 8 * DAY), &page.next_cursor, &10);
+    assert_eq!(page_ids(&page), vec![&env, rental3, rental4]);
+
+    // Extensions move a rental back in the expiry order
+    client.extend_rental(&borrower, &rental3, &(6 * DAY));
+    let page = client.get_overdue_rentals(&(start + 30 * DAY), &0, &10);
+    assert_eq!(page_ids(&page), vec![&env, rental2, rental4, rental1, rental3]);
+
+    // Settled rentals leave both indexes
+    client.return_tokens(&borrower, &rental4);
+    let page = client.get_lender_rentals(&lender, &0, &10);
+    assert_eq!(page_ids(&page), vec![&env, rental1, rental3]);
+    let page = client.get_overdue_rentals(&(start + 30 * DAY), &0, &10);
+    assert_eq!(page_ids(&page), vec![&env, rental2, rental1, rental3]);
+
+    // Rentals still running at `now` aren't overdue, even on their last day
+    assert_eq!(client.get_overdue_rentals(&(start + 3 * DAY), &0, &10).rentals.len(), 0);
+
+    // A cursor whose rental has been settled resumes after it
+    let page = client.get_overdue_rentals(&(start + 30 * DAY), &0, &2);
+    assert_eq!(page_ids(&page), vec![&env, rental2, rental1]);
+    client.return_tokens(&borrower, &rental1);
+    client.return_tokens(&borrower, &rental2);
+    let page = client.get_overdue_rentals(&(start + 30 * DAY), &page.next_cursor, &10);
+    assert_eq!(page_ids(&page), vec![&env, rental3]);
+}
+
+#[test]
+fn test_user_history() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let lender = Address::generate(&env);
+    let borrower = Address::generate(&env);
+
+    let client = create_contract(&env, &admin);
+
+    let token_admin = Address::generate(&env);
+    let token_id = env.register_stellar_asset_contract_v2(token_admin.clone()).address();
+    support_token(&client, &token_id);
+    let token_client = token::StellarAssetClient::new(&env, &token_id);
+    token_client.mint(&lender, &100_000);
+    token_client.mint(&borrower, &100_000);
+
+    let listing_id = client.create_listing(
+        &lender, &token_id, &10000, &100, &DAY, &(30 * DAY), &1000, &vec![&env, token_id.clone()], &ListingOptions::default()
+    );
+    let other_listing_id = client.create_listing(
+        &lender, &token_id, &1000, &100, &DAY, &(30 * DAY), &1000, &vec![&env, token_id.clone()], &ListingOptions::default()
+    );
+
+    // History fills pages of 50 in the order rentals were taken out
+    let mut rental_ids = Vec::new(&env);
+    for _ in 0..55 {
+        rental_ids.push_back(client.rent_tokens(&borrower, &listing_id, &100, &DAY, &token_id));
+    }
+    assert_eq!(client.get_user_rental_count(&borrower), 55);
+    assert_eq!(client.get_user_rentals(&borrower, &0), rental_ids.slice(0..50));
+    assert_eq!(client.get_user_rentals(&borrower, &1), rental_ids.slice(50..55));
+    assert_eq!(client.get_user_rentals(&borrower, &2).len(), 0);
+    assert_eq!(client.get_user_listing_count(&lender), 2);
+    assert_eq!(client.get_user_listings(&lender, &0), vec![&env, listing_id, other_listing_id]);
+
+    // Settled rentals stay in the history but leave the active subset
+    for id in rental_ids.slice(0..53).iter() {
+        client.return_tokens(&borrower, &id);
+    }
+    assert_eq!(client.get_user_rental_count(&borrower), 55);
+    let page = client.get_active_rentals(&borrower, &0, &1);
+    assert_eq!(page.rentals.get(0).unwrap().rental_id, rental_ids.get(53).unwrap());
+    let page = client.get_active_rentals(&borrower, &page.next_cursor, &10);
+    assert_eq!(page.rentals.get(0).unwrap().rental_id, rental_ids.get(54).unwrap());
+    assert_eq!(page.next_cursor, 0);
+
+    // Likewise for listings once they close
+    client.cancel_listing(&lender, &other_listing_id);
+    let page = client.get_open_listings(&lender, &0, &10);
+    assert_eq!(page.listings.len(), 1);
+    assert_eq!(page.listings.get(0).unwrap().listing_id, listing_id);
+    assert_eq!(client.get_user_listing_count(&lender), 2);
+}
+
+#[test]
+fn test_listing_management() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let lender = Address::generate(&env);
+    let other = Address::generate(&env);
+    let borrower = Address::generate(&env);
+
+    let client = create_contract(&env, &admin);
+
+    let token_admin = Address::generate(&env);
+    let token_id = env.register_stellar_asset_contract_v2(token_admin.clone()).address();
+    support_token(&client, &token_id);
+    let token_client = token::StellarAssetClient::new(&env, &token_id);
+    let token = token::Client::new(&env, &token_id);
+    token_client.mint(&lender, &10_000);
+    token_client.mint(&borrower, &10_000);
+
+    let listing_id = client.create_listing(
+        &lender, &token_id, &1000, &100, &DAY, &(30 * DAY), &1000, &vec![&env, token_id.clone()], &ListingOptions::default()
+    );
+
+    // Terms can be changed in place, within the token limits
+    client.update_listing(&lender, &listing_id, &200, &(2 * DAY), &(10 * DAY), &2000);
+    let listing = client.get_listing(&listing_id).unwrap();
+    assert_eq!(listing.rental_rate, 200);
+    assert_eq!(listing.min_duration, 2 * DAY);
+    assert_eq!(listing.max_duration, 10 * DAY);
+    assert_eq!(listing.collateral_rate, 2000);
+    assert_eq!(
+        client.try_update_listing(&other, &listing_id, &200, &DAY, &(10 * DAY), &2000),
+        Err(Ok(Error::UnauthorizedAccess))
+    );
+    assert_eq!(
+        client.try_update_listing(&lender, &listing_id, &200, &DAY, &(400 * DAY), &2000),
+        Err(Ok(Error::InvalidDuration))
+    );
+    assert_eq!(
+        client.try_update_listing(&lender, &listing_id, &200, &DAY, &(10 * DAY), &0),
+        Err(Ok(Error::InsufficientCollateral))
+    );
+
+    // Top-ups and withdrawals move escrow in and out
+    client.top_up_listing(&lender, &listing_id, &500);
+    let listing = client.get_listing(&listing_id).unwrap();
+    assert_eq!((listing.amount, listing.available_amount), (1500, 1500));
+    client.withdraw_from_listing(&lender, &listing_id, &700);
+    let listing = client.get_listing(&listing_id).unwrap();
+    assert_eq!((listing.amount, listing.available_amount), (800, 800));
+    assert_eq!(token.balance(&lender), 9200);
+    assert_eq!(
+        client.try_withdraw_from_listing(&lender, &listing_id, &900),
+        Err(Ok(Error::InvalidAmount))
+    );
+    assert_eq!(
+        client.try_top_up_listing(&lender, &listing_id, &0),
+        Err(Ok(Error::InvalidAmount))
+    );
+
+    // Terms are frozen while tokens are rented out; amounts are not
+    let rental_id = client.rent_tokens(&borrower, &listing_id, &300, &(2 * DAY), &token_id);
+    assert_eq!(client.get_listing(&listing_id).unwrap().rented_amount, 300);
+    assert_eq!(
+        client.try_update_listing(&lender, &listing_id, &100, &DAY, &(10 * DAY), &2000),
+        Err(Ok(Error::ListingRented))
+    );
+    client.withdraw_from_listing(&lender, &listing_id, &200);
+    let listing = client.get_listing(&listing_id).unwrap();
+    assert_eq!((listing.amount, listing.available_amount), (600, 300));
+
+    // Once the principal is repaid, terms can change again
+    client.return_tokens(&borrower, &rental_id);
+    assert_eq!(client.get_listing(&listing_id).unwrap().rented_amount, 0);
+    client.update_listing(&lender, &listing_id, &100, &DAY, &(10 * DAY), &2000);
+
+    // Withdrawing the rest closes the listing
+    client.withdraw_from_listing(&lender, &listing_id, &300);
+    let listing = client.get_listing(&listing_id).unwrap();
+    assert!(!listing.is_available);
+    assert_eq!(client.list_available(&0, &10, &ListingFilter::default()).listings.len(), 0);
+    assert_eq!(
+        client.try_top_up_listing(&lender, &listing_id, &500),
+        Err(Ok(Error::ListingNotAvailable))
+    );
+
+    // Only withdrawals are allowed once the platform is winding down
+    let listing_id = client.create_listing(
+        &lender, &token_id, &1000, &100, &DAY, &(30 * DAY), &1000, &vec![&env, token_id.clone()], &ListingOptions::default()
+    );
+    client.set_mode(&admin, &ContractMode::WithdrawOnly);
+    assert_eq!(
+        client.try_top_up_listing(&lender, &listing_id, &500),
+        Err(Ok(Error::ContractPaused))
+    );
+    assert_eq!(
+        client.try_update_listing(&lender, &listing_id, &100, &DAY, &(10 * DAY), &2000),
+        Err(Ok(Error::ContractPaused))
+    );
+    client.withdraw_from_listing(&lender, &listing_id, &1000);
+    assert!(!client.get_listing(&listing_id).unwrap().is_available);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #14)")]
+fn test_constructor_rejects_invalid_config() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let mut config = default_config(&admin);
+    config.platform_fee_rate = 10_000;
+
+    env.register(XRentContract, (config,));
+}
+
+#[test]
+#[should_panic(expected = "Error(Auth, InvalidAction)")]
+fn test_constructor_requires_admin_auth() {
+    let env = Env::default();
+
+    let admin = Address::generate(&env);
+    create_contract(&env, &admin);
+}
+
+#[test]
+fn test_invalid_amount() {
+    let env = Env::default();
+    env.mock_all_auths();
+    
+    let admin = Address::generate(&env);
+    let lender = Address::generate(&env);
+    
+    let client = create_contract(&env, &admin);
+
+    let token_admin = Address::generate(&env);
+    let token_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let token_id = token_contract.address();
+    support_token(&client, &token_id);
+
+    // Listing with invalid amount (0) is rejected
+    assert_eq!(
+        client.try_create_listing(&lender, &token_id, &0, &500, &DAY, &(30 * DAY), &1500, &vec![&env, token_id.clone()], &ListingOptions::default()),
+        Err(Ok(Error::InvalidAmount))
+    );
+}
+
+#[test]
+fn test_rental_errors() {
+    let env = Env::default();
+    env.mock_all_auths();
+    
+    let admin = Address::generate(&env);
+    let lender = Address::generate(&env);
+    let borrower = Address::generate(&env);
+    let stranger = Address::generate(&env);
+    
+    let client = create_contract(&env, &admin);
+
+    let token_admin = Address::generate(&env);
+    let token_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let token_id = token_contract.address();
+    support_token(&client, &token_id);
+    let token_client = token::StellarAssetClient::new(&env, &token_id);
+    
+    token_client.mint(&lender, &1000);
+    token_client.mint(&borrower, &1000);
+
+    // Collateral below the platform minimum
+    assert_eq!(
+        client.try_create_listing(&lender, &token_id, &100, &500, &DAY, &(30 * DAY), &500, &vec![&env, token_id.clone()], &ListingOptions::default()),
+        Err(Ok(Error::InsufficientCollateral))
+    );
+
+    let listing_id = client.create_listing(
+        &lender, &token_id, &100, &500, &DAY, &(30 * DAY), &1500, &vec![&env, token_id.clone()], &ListingOptions::default()
+    );
+
+    // Unknown ids and out-of-range durations
+    assert_eq!(client.try_rent_tokens(&borrower, &99, &100, &(7 * DAY), &token_id), Err(Ok(Error::ListingNotFound)));
+    assert_eq!(client.try_rent_tokens(&borrower, &listing_id, &100, &(31 * DAY), &token_id), Err(Ok(Error::InvalidDuration)));
+    assert_eq!(client.try_return_tokens(&borrower, &99), Err(Ok(Error::RentalNotFound)));
+
+    let rental_id = client.rent_tokens(&borrower, &listing_id, &100, &(7 * DAY), &token_id);
+
+    // Listing is taken and the rental belongs to the borrower
+    assert_eq!(client.try_rent_tokens(&stranger, &listing_id, &100, &(7 * DAY), &token_id), Err(Ok(Error::ListingNotAvailable)));
+    assert_eq!(client.try_cancel_listing(&lender, &listing_id), Err(Ok(Error::ListingNotAvailable)));
+    assert_eq!(client.try_return_tokens(&stranger, &rental_id), Err(Ok(Error::UnauthorizedReturn)));
+    assert_eq!(client.try_settle_default(&rental_id), Err(Ok(Error::RentalActive)));
+
+    client.return_tokens(&borrower, &rental_id);
+    assert_eq!(client.try_return_tokens(&borrower, &rental_id), Err(Ok(Error::RentalExpired)));
+}
+
+#[test]
+fn test_withdraw_fees() {
+    let env = Env::default();
+    env.mock_all_auths();
+    
+    let admin = Address::generate(&env);
+    let lender = Address::generate(&env);
+    let borrower = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    
+    let client = create_contract(&env, &admin);
+
+    let token_admin = Address::generate(&env);
+    let token_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let token_id = token_contract.address();
+    support_token(&client, &token_id);
+    let token_client = token::StellarAssetClient::new(&env, &token_id);
+    let token = token::Client::new(&env, &token_id);
+    
+    token_client.mint(&lender, &10_000);
+    token_client.mint(&borrower, &10_000);
+
+    let listing_id = client.create_listing(
+        &lender, &token_id, &10_000, &500, &DAY, &(30 * DAY), &1500, &vec![&env, token_id.clone()], &ListingOptions::default()
+    );
+    client.rent_tokens(&borrower, &listing_id, &10_000, &(7 * DAY), &token_id);
+
+    // Rental fee: 10_000 * 5% * 7 = 3_500, platform fee: 2.5% of that = 87
+    assert_eq!(client.get_accrued_fees(&token_id), 87);
+    assert_eq!(token.balance(&lender), 3_500);
+
+    assert_eq!(
+        client.try_withdraw_fees(&token_id, &treasury, &88),
+        Err(Ok(Error::InsufficientBalance))
+    );
+    assert_eq!(
+        client.try_withdraw_fees(&token_id, &treasury, &0),
+        Err(Ok(Error::InvalidAmount))
+    );
+
+    client.withdraw_fees(&token_id, &treasury, &50);
+    assert_eq!(env.auths()[0].0, admin);
+    assert_eq!(client.get_accrued_fees(&token_id), 37);
+    assert_eq!(token.balance(&treasury), 50);
+
+    client.withdraw_fees(&token_id, &treasury, &37);
+    assert_eq!(client.get_accrued_fees(&token_id), 0);
+    assert_eq!(token.balance(&treasury), 87);
+}
+
+#[test]
+fn test_update_config() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+
+    let admin = Address::generate(&env);
+    let client = create_contract(&env, &admin);
+
+    client.set_platform_fee_rate(&500);
+    assert_eq!(env.auths()[0].0, admin);
+    client.set_min_collateral_rate(&2000);
+    client.set_max_rental_duration(&(90 * DAY));
+    client.set_default_grace_period(&3600);
+
+    let config = client.get_config().unwrap();
+    assert_eq!(config.platform_fee_rate, 500);
+    assert_eq!(config.min_collateral_rate, 2000);
+    assert_eq!(config.max_rental_duration, 90 * DAY);
+    assert_eq!(config.default_grace_period, 3600);
+
+    // Out-of-bounds values are rejected and leave the config untouched
+    assert_eq!(client.try_set_platform_fee_rate(&-1), Err(Ok(Error::InvalidConfig)));
+    assert_eq!(client.try_set_platform_fee_rate(&2001), Err(Ok(Error::InvalidConfig)));
+    assert_eq!(client.try_set_min_collateral_rate(&0), Err(Ok(Error::InvalidConfig)));
+    assert_eq!(client.try_set_max_rental_duration(&0), Err(Ok(Error::InvalidConfig)));
+    assert_eq!(client.try_set_max_rental_duration(&(3651 * DAY)), Err(Ok(Error::InvalidConfig)));
+    assert_eq!(client.try_set_default_grace_period(&(31 * 24 * 60 * 60)), Err(Ok(Error::InvalidConfig)));
+    assert_eq!(client.get_config().unwrap(), config);
+}
+
+#[test]
+fn test_admin_transfer() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+
+    let admin = Address::generate(&env);
+    let new_admin = Address::generate(&env);
+    let client = create_contract(&env, &admin);
+
+    assert_eq!(client.try_accept_admin(), Err(Ok(Error::NoPendingAdmin)));
+
+    client.propose_admin(&new_admin);
+    assert_eq!(env.auths()[0].0, admin);
+    assert_eq!(client.get_pending_admin(), Some(new_admin.clone()));
+
+    // Proposal alone doesn't change the admin
+    assert_eq!(client.get_config().unwrap().admin, admin);
+
+    client.accept_admin();
+    assert_eq!(env.auths()[0].0, new_admin);
+    assert_eq!(client.get_config().unwrap().admin, new_admin);
+    assert_eq!(client.get_pending_admin(), None);
+
+    // The new admin now authorizes config changes
+    client.set_platform_fee_rate(&100);
+    assert_eq!(env.auths()[0].0, new_admin);
+}
+
+#[test]
+fn test_pause_modes() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let pauser = Address::generate(&env);
+    let lender = Address::generate(&env);
+    let borrower = Address::generate(&env);
+
+    let client = create_contract(&env, &admin);
+    assert_eq!(client.get_mode(), ContractMode::Normal);
+
+    let token_admin = Address::generate(&env);
+    let token_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let token_id = token_contract.address();
+    support_token(&client, &token_id);
+    let token_client = token::StellarAssetClient::new(&env, &token_id);
+
+    token_client.mint(&lender, &1000);
+    token_client.mint(&borrower, &1000);
+
+    let listing_id = client.create_listing(
+        &lender, &token_id, &100, &500, &DAY, &(30 * DAY), &1500, &vec![&env, token_id.clone()], &ListingOptions::default()
+    );
+    let rental_id = client.rent_tokens(&borrower, &listing_id, &100, &(7 * DAY), &token_id);
+    let open_listing_id = client.create_listing(
+        &lender, &token_id, &100, &500, &DAY, &(30 * DAY), &1500, &vec![&env, token_id.clone()], &ListingOptions::default()
+    );
+
+    // Only admin and pauser can change the mode
+    assert_eq!(
+        client.try_set_mode(&lender, &ContractMode::Paused),
+        Err(Ok(Error::UnauthorizedAccess))
+    );
+    client.set_pauser(&Some(pauser.clone()));
+    assert_eq!(client.get_pauser(), Some(pauser.clone()));
+
+    client.set_mode(&pauser, &ContractMode::Paused);
+    assert_eq!(client.get_mode(), ContractMode::Paused);
+
+    // New exposure is blocked
+    assert_eq!(
+        client.try_create_listing(&lender, &token_id, &100, &500, &DAY, &(30 * DAY), &1500, &vec![&env, token_id.clone()], &ListingOptions::default()),
+        Err(Ok(Error::ContractPaused))
+    );
+    assert_eq!(
+        client.try_rent_tokens(&borrower, &open_listing_id, &100, &(7 * DAY), &token_id),
+        Err(Ok(Error::ContractPaused))
+    );
+
+    // Existing positions can still be adjusted
+    client.extend_rental(&borrower, &rental_id, &DAY);
+    client.update_listing(&lender, &open_listing_id, &400, &DAY, &(30 * DAY), &1500);
+
+    client.set_mode(&pauser, &ContractMode::WithdrawOnly);
+
+    // Adjustments are blocked once the platform is winding down
+    assert_eq!(
+        client.try_extend_rental(&borrower, &rental_id, &DAY),
+        Err(Ok(Error::ContractPaused))
+    );
+    assert_eq!(
+        client.try_update_listing(&lender, &open_listing_id, &500, &DAY, &(30 * DAY), &1500),
+        Err(Ok(Error::ContractPaused))
+    );
+
+    // The pauser can't relax the mode
+    assert_eq!(
+        client.try_set_mode(&pauser, &ContractMode::Normal),
+        Err(Ok(Error::UnauthorizedAccess))
+    );
+    assert_eq!(
+        client.try_set_mode(&pauser, &ContractMode::Paused),
+        Err(Ok(Error::UnauthorizedAccess))
+    );
+
+    // Users can still unwind
+    client.return_tokens(&borrower, &rental_id);
+    client.cancel_listing(&lender, &open_listing_id);
+
+    client.set_mode(&admin, &ContractMode::Normal);
+    client.create_listing(&lender, &token_id, &100, &500, &DAY, &(30 * DAY), &1500, &vec![&env, token_id.clone()], &ListingOptions::default());
+}
+
+#[test]
+fn test_migrate_legacy_storage() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let lender = Address::generate(&env);
+    let borrower = Address::generate(&env);
+    let token_id = Address::generate(&env);
+
+    let client = create_contract(&env, &admin);
+    assert_eq!(client.version(), 2);
+    assert_eq!(client.storage_version(), 2);
+    assert_eq!(client.try_migrate(&10), Err(Ok(Error::MigrationNotNeeded)));
+
+    // Simulate storage left behind by the original deployment
+    let legacy_listing = migration::TokenListingV1 {
+        listing_id: 1,
+        lender: lender.clone(),
+        token_address: token_id.clone(),
+        amount: 100,
+        rental_rate: 500,
+        min_duration: 1,
+        max_duration: 30,
+        collateral_rate: 1500,
+        is_available: false,
+    };
+    let legacy_rental = migration::RentalAgreementV1 {
+        rental_id: 1,
+        lender: lender.clone(),
+        borrower: borrower.clone(),
+        token_address: token_id.clone(),
+        amount: 100,
+        collateral_amount: 15,
+        rental_fee: 35,
+        start_time: 0,
+        end_time: 7 * 24 * 60 * 60,
+        is_active: true,
+        is_completed: false,
+    };
+    let legacy_config = migration::PlatformConfigV1 {
+        admin: admin.clone(),
+        platform_fee_rate: 250,
+        min_collateral_rate: 1000,
+        max_rental_duration: 365,
+    };
+    env.as_contract(&client.address, || {
+        env.storage().instance().set(&CONFIG, &legacy_config);
+        env.storage().persistent().set(&(LISTING, 1u64), &legacy_listing);
+        env.storage().persistent().set(&(RENTAL, 1u64), &legacy_rental);
+        env.storage().instance().set(&NEXT_LISTING_ID, &2u64);
+        env.storage().instance().set(&NEXT_RENTAL_ID, &2u64);
+        env.storage().instance().remove(&SCHEMA);
+    });
+    assert_eq!(client.storage_version(), 1);
+
+    // Entrypoints refuse to touch records until the migration completes
+    assert_eq!(
+        client.try_return_tokens(&borrower, &1),
+        Err(Ok(Error::MigrationPending))
+    );
+
+    // One record per batch: listing first, then the rental
+    assert!(!client.migrate(&1));
+    assert_eq!(client.storage_version(), 1);
+
+    // No new records can be written mid-migration
+    assert_eq!(
+        client.try_create_listing(&lender, &token_id, &100, &500, &DAY, &(30 * DAY), &1500, &vec![&env, token_id.clone()], &ListingOptions::default()),
+        Err(Ok(Error::MigrationPending))
+    );
+
+    // Ids handed out after the migration started are already current
+    env.as_contract(&client.address, || {
+        env.storage().instance().set(&NEXT_LISTING_ID, &3u64);
+    });
+    assert!(client.migrate(&1));
+    assert_eq!(client.storage_version(), 2);
+
+    let config = client.get_config().unwrap();
+    assert_eq!(config.admin, admin);
+    assert_eq!(config.default_grace_period, 3 * 24 * 60 * 60);
+    assert_eq!(config.max_rental_duration, 365 * DAY);
+
+    let listing = client.get_listing(&1).unwrap();
+    assert_eq!(listing.lender, lender);
+    assert_eq!(listing.amount, 100);
+    assert!(!listing.is_available);
+    assert_eq!(listing.collateral_tokens, vec![&env, token_id.clone()]);
+    assert_eq!(listing.max_duration, 30 * DAY);
+
+    let rental = client.get_rental(&1).unwrap();
+    assert_eq!(rental.borrower, borrower);
+    assert_eq!(rental.collateral_token, token_id);
+    assert_eq!(rental.collateral_amount, 15);
+    assert_eq!(rental.rental_rate, 500);
+    assert_eq!(rental.platform_fee_rate, 250);
+    assert_eq!(rental.status, RentalStatus::Active);
+
+    // Legacy records join their owners' paged histories
+    assert_eq!(client.get_user_listings(&lender, &0), vec![&env, 1]);
+    assert_eq!(client.get_user_rentals(&borrower, &0), vec![&env, 1]);
+}
+
+#[test]
+fn test_storage_ttl() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let lender = Address::generate(&env);
+    let borrower = Address::generate(&env);
+
+    let client = create_contract(&env, &admin);
+
+    let token_admin = Address::generate(&env);
+    let token_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let token_id = token_contract.address();
+    support_token(&client, &token_id);
+    let token_client = token::StellarAssetClient::new(&env, &token_id);
+
+    token_client.mint(&lender, &1000);
+    token_client.mint(&borrower, &1000);
+
+    let listing_id = client.create_listing(
+        &lender, &token_id, &100, &10, &DAY, &(300 * DAY), &1500, &vec![&env, token_id.clone()], &ListingOptions::default()
+    );
+    let rental_id = client.rent_tokens(&borrower, &listing_id, &100, &(300 * DAY), &token_id);
+
+    let rental_ttl = || env.as_contract(&client.address, || {
+        env.storage().persistent().get_ttl(&(RENTAL, rental_id))
+    });
+
+    // The rental record outlives its 300 day term plus the settlement margin
+    let term = 300 * DAY_IN_LEDGERS;
+    assert!(rental_ttl() >= term + RENTAL_TTL_MARGIN);
+    env.as_contract(&client.address, || {
+        let page_key = history::HistoryEntryKey::Page(HistoryKey::Rentals(borrower.clone()), 0);
+        assert!(env.storage().persistent().get_ttl(&page_key) >= term);
+        assert!(env.storage().persistent().get_ttl(&(LISTING, listing_id)) >= term);
+        assert!(env.storage().instance().get_ttl() >= INSTANCE_LIFETIME_THRESHOLD);
+    });
+
+    // Escrowed listing funds and platform fees live as long as allowed
+    let open_id = client.create_listing(
+        &lender, &token_id, &500, &500, &DAY, &(300 * DAY), &1500, &vec![&env, token_id.clone()], &ListingOptions::default()
+    );
+    client.rent_tokens(&borrower, &open_id, &400, &(10 * DAY), &token_id);
+    assert!(client.get_accrued_fees(&token_id) > 0);
+    env.as_contract(&client.address, || {
+        let max_ttl = env.storage().max_ttl();
+        assert_eq!(env.storage().persistent().get_ttl(&(LISTING, open_id)), max_ttl);
+        assert_eq!(env.storage().persistent().get_ttl(&(ACCRUED_FEES, &token_id)), max_ttl);
+    });
+
+    // Twenty days later, anyone can keep the contract and the rental alive
+    env.ledger().with_mut(|li| {
+        li.sequence_number += 20 * DAY_IN_LEDGERS;
+        li.timestamp += 20 * 24 * 60 * 60;
+    });
+    client.bump_rental(&rental_id);
+    assert!(rental_ttl() >= term - 20 * DAY_IN_LEDGERS + RENTAL_TTL_MARGIN);
+    env.as_contract(&client.address, || {
+        assert!(env.storage().instance().get_ttl() >= INSTANCE_LIFETIME_THRESHOLD);
+    });
+
+    client.bump_listing(&listing_id);
+    assert_eq!(client.try_bump_rental(&99), Err(Ok(Error::RentalNotFound)));
+}
+
+#[test]
+fn test_token_registry() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let lender = Address::generate(&env);
+    let borrower = Address::generate(&env);
+
+    let client = create_contract(&env, &admin);
+
+    let token_admin = Address::generate(&env);
+    let token_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let token_id = token_contract.address();
+    let token_client = token::StellarAssetClient::new(&env, &token_id);
+
+    token_client.mint(&lender, &100_000);
+    token_client.mint(&borrower, &100_000);
+
+    // Unknown tokens can't be listed
+    assert_eq!(
+        client.try_create_listing(&lender, &token_id, &10_000, &500, &DAY, &(30 * DAY), &1500, &vec![&env, token_id.clone()], &ListingOptions::default()),
+        Err(Ok(Error::TokenNotSupported))
+    );
+
+    let token_config = TokenConfig {
+        token_address: token_id.clone(),
+        min_amount: 1_000,
+        min_collateral_rate: Some(2000),
+        max_rental_duration: Some(14 * DAY),
+        platform_fee_rate: Some(1000),
+        maintenance_collateral_rate: 1500,
+        liquidation_bonus_rate: 500,
+        price_feed: None,
+        max_price_age: None,
+    };
+    client.set_token_config(&token_config);
+    assert_eq!(env.auths()[0].0, admin);
+    assert_eq!(client.get_token_config(&token_id), Some(token_config.clone()));
+    assert_eq!(client.list_supported_tokens(), Vec::from_array(&env, [token_config.clone()]));
+
+    let mut invalid = token_config.clone();
+    invalid.platform_fee_rate = Some(5000);
+    assert_eq!(client.try_set_token_config(&invalid), Err(Ok(Error::InvalidConfig)));
+
+    // Token parameters override the platform defaults
+    assert_eq!(
+        client.try_create_listing(&lender, &token_id, &999, &500, &DAY, &(14 * DAY), &2000, &vec![&env, token_id.clone()], &ListingOptions::default()),
+        Err(Ok(Error::InvalidAmount))
+    );
+    assert_eq!(
+        client.try_create_listing(&lender, &token_id, &10_000, &500, &DAY, &(14 * DAY), &1500, &vec![&env, token_id.clone()], &ListingOptions::default()),
+        Err(Ok(Error::InsufficientCollateral))
+    );
+    assert_eq!(
+        client.try_create_listing(&lender, &token_id, &10_000, &500, &DAY, &(30 * DAY), &2000, &vec![&env, token_id.clone()], &ListingOptions::default()),
+        Err(Ok(Error::InvalidDuration))
+    );
+
+    let listing_id = client.create_listing(&lender, &token_id, &10_000, &500, &DAY, &(14 * DAY), &2000, &vec![&env, token_id.clone()], &ListingOptions::default());
+    let open_listing_id = client.create_listing(&lender, &token_id, &10_000, &500, &DAY, &(14 * DAY), &2000, &vec![&env, token_id.clone()], &ListingOptions::default());
+    client.rent_tokens(&borrower, &listing_id, &10_000, &(7 * DAY), &token_id);
+
+    // Rental fee 3_500, platform fee at the 10% token override
+    assert_eq!(client.get_accrued_fees(&token_id), 350);
+
+    // Delisted tokens can be unwound but not rented
+    client.remove_token(&token_id);
+    assert_eq!(client.list_supported_tokens().len(), 0);
+    assert_eq!(
+        client.try_rent_tokens(&borrower, &open_listing_id, &10_000, &(7 * DAY), &token_id),
+        Err(Ok(Error::TokenNotSupported))
+    );
+    client.cancel_listing(&lender, &open_listing_id);
+    assert_eq!(client.try_remove_token(&token_id), Err(Ok(Error::TokenNotSupported)));
+
+    // Tokens without overrides follow the platform bounds
+    support_token(&client, &token_id);
+    client.set_min_collateral_rate(&2000);
+    client.set_max_rental_duration(&(14 * DAY));
+    assert_eq!(
+        client.try_create_listing(&lender, &token_id, &10_000, &500, &DAY, &(14 * DAY), &1500, &vec![&env, token_id.clone()], &ListingOptions::default()),
+        Err(Ok(Error::InsufficientCollateral))
+    );
+    assert_eq!(
+        client.try_create_listing(&lender, &token_id, &10_000, &500, &DAY, &(15 * DAY), &2000, &vec![&env, token_id.clone()], &ListingOptions::default()),
+        Err(Ok(Error::InvalidDuration))
+    );
+    client.create_listing(&lender, &token_id, &10_000, &500, &DAY, &(14 * DAY), &2000, &vec![&env, token_id.clone()], &ListingOptions::default());
+
+    // The platform minimum can't drop below a following token's maintenance rate
+    assert_eq!(client.try_set_min_collateral_rate(&700), Err(Ok(Error::InvalidConfig)));
+}
+
+#[test]
+fn test_separate_collateral_token() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let lender = Address::generate(&env);
+    let borrower = Address::generate(&env);
+
+    let client = create_contract(&env, &admin);
+
+    let token_admin = Address::generate(&env);
+    let token_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let token_id = token_contract.address();
+    support_token(&client, &token_id);
+    let collateral_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let collateral_id = collateral_contract.address();
+    let other_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let other_id = other_contract.address();
+
+    let token = token::Client::new(&env, &token_id);
+    let collateral = token::Client::new(&env, &collateral_id);
+    token::StellarAssetClient::new(&env, &token_id).mint(&lender, &10_000);
+    token::StellarAssetClient::new(&env, &token_id).mint(&borrower, &10_000);
+    token::StellarAssetClient::new(&env, &collateral_id).mint(&borrower, &10_000);
+
+    // Collateral assets must be in the registry too
+    assert_eq!(
+        client.try_create_listing(&lender, &token_id, &10_000, &500, &DAY, &(30 * DAY), &1500, &vec![&env, collateral_id.clone()], &ListingOptions::default()),
+        Err(Ok(Error::TokenNotSupported))
+    );
+    assert_eq!(
+        client.try_create_listing(&lender, &token_id, &10_000, &500, &DAY, &(30 * DAY), &1500, &vec![&env], &ListingOptions::default()),
+        Err(Ok(Error::CollateralNotAccepted))
+    );
+    support_token(&client, &collateral_id);
+
+    // Both assets trade at the same price
+    let oracle = create_oracle(&env, &client);
+    set_price(&env, &oracle, &token_id, 100_000_000_000_000);
+    set_price(&env, &oracle, &collateral_id, 100_000_000_000_000);
+
+    let listing_id = client.create_listing(
+        &lender, &token_id, &10_000, &500, &DAY, &(30 * DAY), &1500, &vec![&env, collateral_id.clone()], &ListingOptions::default()
+    );
+    let listing = client.get_listing(&listing_id).unwrap();
+    assert_eq!(listing.collateral_tokens, vec![&env, collateral_id.clone()]);
+
+    assert_eq!(
+        client.try_rent_tokens(&borrower, &listing_id, &10_000, &(7 * DAY), &other_id),
+        Err(Ok(Error::CollateralNotAccepted))
+    );
+    let rental_id = client.rent_tokens(&borrower, &listing_id, &10_000, &(7 * DAY), &collateral_id);
+
+    // Fees are paid in the rented token, collateral in the collateral token
+    let rental = client.get_rental(&rental_id).unwrap();
+    assert_eq!(rental.collateral_token, collateral_id);
+    assert_eq!(rental.collateral_amount, 1_500);
+    assert_eq!(token.balance(&borrower), 10_000 + 10_000 - 3_500 - 87);
+    assert_eq!(collateral.balance(&borrower), 10_000 - 1_500);
+    assert_eq!(collateral.balance(&client.address), 1_500);
+
+    // A late return forfeits part of the collateral asset to the lender
+    env.ledger().with_mut(|li| li.timestamp = rental.end_time + 1);
+    client.return_tokens(&borrower, &rental_id);
+    assert_eq!(collateral.balance(&lender), 150);
+    assert_eq!(collateral.balance(&borrower), 10_000 - 150);
+    assert_eq!(collateral.balance(&client.address), 0);
+    assert_eq!(token.balance(&lender), 10_000 + 3_500);
+
+    let rental = client.get_rental(&rental_id).unwrap();
+    assert_eq!(rental.status, RentalStatus::ReturnedLate);
+    assert_eq!(rental.penalty_paid, 150);
+    assert_eq!(rental.collateral_refunded, 1_350);
+}
+
+#[test]
+fn test_oracle_collateral_valuation() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let lender = Address::generate(&env);
+    let borrower = Address::generate(&env);
+
+    let client = create_contract(&env, &admin);
+
+    let token_admin = Address::generate(&env);
+    let usdc_id = env.register_stellar_asset_contract_v2(token_admin.clone()).address();
+    let xlm_id = env.register_stellar_asset_contract_v2(token_admin.clone()).address();
+    support_token(&client, &usdc_id);
+    support_token(&client, &xlm_id);
+
+    let xlm = token::Client::new(&env, &xlm_id);
+    token::StellarAssetClient::new(&env, &usdc_id).mint(&lender, &10_000_000_000);
+    token::StellarAssetClient::new(&env, &usdc_id).mint(&borrower, &10_000_000_000);
+    token::StellarAssetClient::new(&env, &xlm_id).mint(&borrower, &100_000_000_000);
+
+    // 1,000 USDC at 150% collateral, payable in XLM
+    let listing_id = client.create_listing(
+        &lender, &usdc_id, &10_000_000_000, &10, &DAY, &(30 * DAY), &15000, &vec![&env, xlm_id.clone()], &ListingOptions::default()
+    );
+
+    // Cross-asset collateral needs a price feed
+    assert_eq!(
+        client.try_rent_tokens(&borrower, &listing_id, &10_000_000_000, &(7 * DAY), &xlm_id),
+        Err(Ok(Error::OracleNotConfigured))
+    );
+
+    let oracle = create_oracle(&env, &client);
+    set_price(&env, &oracle, &usdc_id, 100_000_000_000_000); // 1 USD
+    assert_eq!(
+        client.try_rent_tokens(&borrower, &listing_id, &10_000_000_000, &(7 * DAY), &xlm_id),
+        Err(Ok(Error::PriceUnavailable))
+    );
+    set_price(&env, &oracle, &xlm_id, 25_000_000_000_000); // 0.25 USD
+
+    // Quotes older than the configured age are rejected
+    env.ledger().with_mut(|li| li.timestamp += 301);
+    assert_eq!(
+        client.try_rent_tokens(&borrower, &listing_id, &10_000_000_000, &(7 * DAY), &xlm_id),
+        Err(Ok(Error::StalePrice))
+    );
+    set_price(&env, &oracle, &usdc_id, 100_000_000_000_000);
+    set_price(&env, &oracle, &xlm_id, 25_000_000_000_000);
+
+    // 1,500 USD of collateral at 0.25 USD per XLM
+    let rental_id = client.rent_tokens(&borrower, &listing_id, &10_000_000_000, &(7 * DAY), &xlm_id);
+    let rental = client.get_rental(&rental_id).unwrap();
+    assert_eq!(rental.collateral_amount, 60_000_000_000);
+    assert_eq!(xlm.balance(&client.address), 60_000_000_000);
+}
+
+#[test]
+fn test_liquidation() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let lender = Address::generate(&env);
+    let borrower = Address::generate(&env);
+    let liquidator = Address::generate(&env);
+
+    let client = create_contract(&env, &admin);
+    let oracle = create_oracle(&env, &client);
+
+    let token_admin = Address::generate(&env);
+    let usdc_id = env.register_stellar_asset_contract_v2(token_admin.clone()).address();
+    let xlm_id = env.register_stellar_asset_contract_v2(token_admin.clone()).address();
+
+    // XLM is priced by its own feed quoting with 7 decimals
+    let xlm_feed_id = env.register(mock_oracle::MockOracle, (7u32,));
+    let xlm_feed = mock_oracle::MockOracleClient::new(&env, &xlm_feed_id);
+
+    let mut usdc_config = default_token_config(&usdc_id);
+    usdc_config.min_collateral_rate = Some(15000);
+    usdc_config.maintenance_collateral_rate = 12000;
+    client.set_token_config(&usdc_config);
+    let mut xlm_config = default_token_config(&xlm_id);
+    xlm_config.price_feed = Some(xlm_feed_id.clone());
+    client.set_token_config(&xlm_config);
+
+    let xlm = token::Client::new(&env, &xlm_id);
+    token::StellarAssetClient::new(&env, &usdc_id).mint(&lender, &10_000_000_000);
+    token::StellarAssetClient::new(&env, &usdc_id).mint(&borrower, &10_000_000_000);
+    token::StellarAssetClient::new(&env, &xlm_id).mint(&borrower, &100_000_000_000);
+
+    set_price(&env, &oracle, &usdc_id, 100_000_000_000_000); // 1 USD
+    set_price(&env, &xlm_feed, &xlm_id, 2_500_000); // 0.25 USD
+
+    let listing_id = client.create_listing(
+        &lender, &usdc_id, &10_000_000_000, &10, &DAY, &(30 * DAY), &15000, &vec![&env, xlm_id.clone()], &ListingOptions::default()
+    );
+    let rental_id = client.rent_tokens(&borrower, &listing_id, &10_000_000_000, &(7 * DAY), &xlm_id);
+    assert_eq!(client.get_rental(&rental_id).unwrap().collateral_amount, 60_000_000_000);
+    assert_eq!(client.get_health_factor(&rental_id), 15000);
+
+    // A 10% drop still leaves the rental above maintenance
+    set_price(&env, &xlm_feed, &xlm_id, 2_250_000);
+    assert_eq!(client.get_health_factor(&rental_id), 13500);
+    assert_eq!(
+        client.try_liquidate(&liquidator, &rental_id),
+        Err(Ok(Error::RentalHealthy))
+    );
+
+    // A 25% drop puts it under
+    set_price(&env, &xlm_feed, &xlm_id, 1_875_000);
+    assert_eq!(client.get_health_factor(&rental_id), 11250);
+    client.liquidate(&liquidator, &rental_id);
+
+    // 5% bonus to the liquidator, the rest of the collateral to the lender
+    assert_eq!(xlm.balance(&liquidator), 3_000_000_000);
+    assert_eq!(xlm.balance(&lender), 57_000_000_000);
+    assert_eq!(xlm.balance(&client.address), 0);
+
+    let rental = client.get_rental(&rental_id).unwrap();
+    assert_eq!(rental.status, RentalStatus::Liquidated);
+    assert_eq!(rental.penalty_paid, 60_000_000_000);
+    assert_eq!(rental.collateral_refunded, 0);
+    assert_eq!(
+        client.try_liquidate(&liquidator, &rental_id),
+        Err(Ok(Error::RentalExpired))
+    );
+}
+
+#[test]
+fn test_token_price_feeds() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let lender = Address::generate(&env);
+    let borrower = Address::generate(&env);
+
+    let client = create_contract(&env, &admin);
+
+    let token_admin = Address::generate(&env);
+    let usdc_id = env.register_stellar_asset_contract_v2(token_admin.clone()).address();
+    let xlm_id = env.register_stellar_asset_contract_v2(token_admin.clone()).address();
+    let eurc_id = env.register_stellar_asset_contract_v2(token_admin.clone()).address();
+    token::StellarAssetClient::new(&env, &usdc_id).mint(&lender, &10_000_000_000);
+    token::StellarAssetClient::new(&env, &usdc_id).mint(&borrower, &10_000_000_000);
+    token::StellarAssetClient::new(&env, &xlm_id).mint(&borrower, &100_000_000_000);
+    token::StellarAssetClient::new(&env, &eurc_id).mint(&borrower, &100_000_000_000);
+
+    // Both tokens are priced by their own feeds; no platform feed is set
+    let usdc_feed_id = env.register(mock_oracle::MockOracle, (14u32,));
+    let usdc_feed = mock_oracle::MockOracleClient::new(&env, &usdc_feed_id);
+    let xlm_feed_id = env.register(mock_oracle::MockOracle, (7u32,));
+    let xlm_feed = mock_oracle::MockOracleClient::new(&env, &xlm_feed_id);
+
+    let mut usdc_config = default_token_config(&usdc_id);
+    usdc_config.price_feed = Some(usdc_feed_id.clone());
+    usdc_config.max_price_age = Some(3600);
+    client.set_token_config(&usdc_config);
+    let mut xlm_config = default_token_config(&xlm_id);
+    xlm_config.price_feed = Some(xlm_feed_id.clone());
+    xlm_config.max_price_age = Some(60);
+    client.set_token_config(&xlm_config);
+    support_token(&client, &eurc_id);
+
+    let mut invalid = xlm_config.clone();
+    invalid.max_price_age = Some(0);
+    assert_eq!(client.try_set_token_config(&invalid), Err(Ok(Error::InvalidConfig)));
+
+    env.ledger().with_mut(|li| li.timestamp = 1_000_000);
+    set_price(&env, &usdc_feed, &usdc_id, 100_000_000_000_000); // 1 USD
+    set_price(&env, &xlm_feed, &xlm_id, 2_500_000); // 0.25 USD
+
+    let listing_id = client.create_listing(
+        &lender, &usdc_id, &10_000_000_000, &10, &DAY, &(30 * DAY), &15000,
+        &vec![&env, xlm_id.clone(), eurc_id.clone()], &ListingOptions::default()
+    );
+    let quote = client.quote_rental(&listing_id, &1_000_000_000, &(7 * DAY), &xlm_id);
+    assert_eq!(quote.collateral_amount, 6_000_000_000);
+
+    // Tokens without a feed still need the platform feed
+    assert_eq!(
+        client.try_quote_rental(&listing_id, &1_000_000_000, &(7 * DAY), &eurc_id),
+        Err(Ok(Error::OracleNotConfigured))
+    );
+
+    // Each feed is held to its own staleness bound
+    env.ledger().with_mut(|li| li.timestamp += 61);
+    assert_eq!(
+        client.try_quote_rental(&listing_id, &1_000_000_000, &(7 * DAY), &xlm_id),
+        Err(Ok(Error::StalePrice))
+    );
+    set_price(&env, &xlm_feed, &xlm_id, 2_500_000);
+    client.rent_tokens(&borrower, &listing_id, &1_000_000_000, &(7 * DAY), &xlm_id);
+}
+
+#[test]
+fn test_settle_default() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let lender = Address::generate(&env);
+    let borrower = Address::generate(&env);
+
+    let client = create_contract(&env, &admin);
+
+    let token_admin = Address::generate(&env);
+    let token_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let token_id = token_contract.address();
+    support_token(&client, &token_id);
+    let token_client = token::StellarAssetClient::new(&env, &token_id);
+    let token = token::Client::new(&env, &token_id);
+
+    token_client.mint(&lender, &10_000);
+    token_client.mint(&borrower, &10_000);
+
+    let listing_id = client.create_listing(
+        &lender, &token_id, &10_000, &500, &DAY, &(30 * DAY), &1500, &vec![&env, token_id.clone()], &ListingOptions::default()
+    );
+    let rental_id = client.rent_tokens(&borrower, &listing_id, &10_000, &(7 * DAY), &token_id);
+    let rental = client.get_rental(&rental_id).unwrap();
+
+    // The borrower keeps the grace period to return late
+    env.ledger().with_mut(|li| li.timestamp = rental.end_time + 24 * 60 * 60);
+    assert_eq!(client.try_settle_default(&rental_id), Err(Ok(Error::RentalActive)));
+
+    // After that, anyone can settle without the borrower's signature
+    env.ledger().with_mut(|li| li.timestamp += 1);
+    env.set_auths(&[]);
+    client.settle_default(&rental_id);
+    assert_eq!(env.auths().len(), 0);
+
+    let rental = client.get_rental(&rental_id).unwrap();
+    assert_eq!(rental.status, RentalStatus::Defaulted);
+    assert_eq!(client.get_listing(&listing_id).unwrap().rented_amount, 0);
+    assert_eq!(rental.settled_at, env.ledger().timestamp());
+    assert_eq!(rental.penalty_paid, 1_500);
+    assert_eq!(rental.collateral_refunded, 0);
+    assert_eq!(token.balance(&lender), 3_500 + 1_500);
+    assert_eq!(token.balance(&client.address), 87);
+
+    // A defaulted rental can't be returned afterwards
+    env.mock_all_auths();
+    assert_eq!(client.try_return_tokens(&borrower, &rental_id), Err(Ok(Error::RentalExpired)));
+    assert_eq!(client.try_settle_default(&rental_id), Err(Ok(Error::RentalExpired)));
+}
+
+#[test]
+fn test_rental_status_transitions() {
+    let settled = [
+        RentalStatus::Returned,
+        RentalStatus::ReturnedLate,
+        RentalStatus::Defaulted,
+        RentalStatus::Liquidated,
+        RentalStatus::Cancelled,
+    ];
 
-    // This should panic due to invalid amount (0)
-    client.create_listing(&lender, &token_id, &0, &500, &1, &30, &1500);
+    // Active rentals can settle into any final state, and only once
+    assert!(!RentalStatus::Active.can_transition_to(RentalStatus::Active));
+    for status in settled {
+        assert!(RentalStatus::Active.can_transition_to(status));
+        for next in settled {
+            assert!(!status.can_transition_to(next));
+        }
+        assert!(!status.can_transition_to(RentalStatus::Active));
+    }
 }